@@ -0,0 +1,91 @@
+//! Compares matching messages against every joke regex one after another
+//! with the `RegexSet` prefilter of `JokeMatcher`.
+//!
+//! Run with `cargo bench`, optionally followed by the path of a config and a corpus file
+//! with one message per line, e.g. `cargo bench -- data/config.json benches/corpus.txt`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use lukas_bot::config::{read_config_from, Joke};
+use lukas_bot::matcher::{match_trigger, JokeMatcher, TriggerEvent};
+use lukas_bot::template::CaptureValues;
+
+const ITERATIONS: u32 = 1000;
+
+/// Matching without the prefilter, like the bot did before.
+fn match_sequential(jokes: &[Joke], event: &TriggerEvent) -> Vec<(Joke, CaptureValues)> {
+    jokes
+        .iter()
+        .filter_map(|joke| match match_trigger(&joke.trigger, event) {
+            Ok(Some(captures)) => Some((joke.clone(), captures)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn time(
+    name: &str,
+    messages: &[TriggerEvent],
+    mut f: impl FnMut(&TriggerEvent) -> usize,
+) -> Duration {
+    let start = Instant::now();
+    let mut matches = 0;
+    for _ in 0..ITERATIONS {
+        for message in messages {
+            matches += black_box(f(black_box(message)));
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<12} {:>10.2?} per message ({} matches)",
+        name,
+        elapsed / (ITERATIONS * messages.len() as u32),
+        matches / ITERATIONS as usize
+    );
+    elapsed
+}
+
+fn main() {
+    // cargo passes `--bench` to the harness
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    let config_path = args
+        .first()
+        .map_or("data/config.json", |path| path.as_str());
+    let corpus_path = args
+        .get(1)
+        .map_or("benches/corpus.txt", |path| path.as_str());
+
+    let config = read_config_from(config_path).expect("invalid config");
+    let messages: Vec<TriggerEvent> = std::fs::read_to_string(corpus_path)
+        .expect("could not read corpus")
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| TriggerEvent {
+            content: line.to_string(),
+            ..Default::default()
+        })
+        .collect();
+    println!(
+        "{} jokes, {} messages, {} iterations",
+        config.jokes.len(),
+        messages.len(),
+        ITERATIONS
+    );
+
+    let allowed = vec![true; config.jokes.len()];
+    let matcher = JokeMatcher::new(config.jokes.clone());
+    let sequential = time("sequential", &messages, |message| {
+        match_sequential(&config.jokes, message).len()
+    });
+    let prefiltered = time("regex set", &messages, |message| {
+        matcher.find(message, &allowed, true).len()
+    });
+    println!(
+        "speedup: {:.2}x",
+        sequential.as_secs_f64() / prefiltered.as_secs_f64()
+    );
+}
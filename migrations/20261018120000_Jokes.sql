@@ -0,0 +1,12 @@
+-- Add migration script here
+
+CREATE TABLE IF NOT EXISTS Jokes (
+    guild_id BIGINT NOT NULL,
+    name TEXT NOT NULL,
+    regex TEXT NOT NULL,
+    -- json array of message variants
+    message TEXT NOT NULL,
+    reply BOOLEAN NOT NULL DEFAULT FALSE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    PRIMARY KEY (guild_id, name)
+);
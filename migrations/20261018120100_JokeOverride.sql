@@ -0,0 +1,10 @@
+-- Add migration script here
+
+CREATE TABLE IF NOT EXISTS JokeOverride (
+    guild_id BIGINT NOT NULL,
+    joke_name TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- overrides the guild chance for this joke, if set
+    chance FLOAT,
+    PRIMARY KEY (guild_id, joke_name)
+);
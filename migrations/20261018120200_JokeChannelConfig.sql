@@ -0,0 +1,10 @@
+-- Add migration script here
+
+-- rules for a single channel, or for every channel in a category
+CREATE TABLE IF NOT EXISTS JokeChannelConfig (
+    channel_id BIGINT NOT NULL PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- overrides the guild chance in this channel, if set
+    chance FLOAT
+);
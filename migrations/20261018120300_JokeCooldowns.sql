@@ -0,0 +1,6 @@
+-- Add migration script here
+
+-- cooldowns in seconds, no cooldown if NULL or 0
+ALTER TABLE JokeConfig ADD COLUMN joke_cooldown INTEGER;
+ALTER TABLE JokeConfig ADD COLUMN user_cooldown INTEGER;
+ALTER TABLE JokeConfig ADD COLUMN guild_cooldown INTEGER;
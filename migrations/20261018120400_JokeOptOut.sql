@@ -0,0 +1,9 @@
+-- Add migration script here
+
+-- users that don't want to be the target of jokes
+CREATE TABLE IF NOT EXISTS JokeOptOut (
+    user_id BIGINT NOT NULL,
+    -- 0 for an opt-out from jokes in all guilds
+    guild_id BIGINT NOT NULL,
+    PRIMARY KEY (user_id, guild_id)
+);
@@ -0,0 +1,7 @@
+-- Add migration script here
+
+-- higher priority jokes are checked first
+ALTER TABLE Jokes ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+-- how to pick jokes if several match, see `MatchStrategy`
+ALTER TABLE JokeConfig ADD COLUMN match_strategy TEXT;
+ALTER TABLE JokeConfig ADD COLUMN max_matches INTEGER;
@@ -0,0 +1,15 @@
+-- Add migration script here
+
+-- every joke the bot made
+CREATE TABLE IF NOT EXISTS JokeEvents (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    joke_name TEXT NOT NULL,
+    -- index of the message variant, NULL if the joke sent no message
+    variant INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS JokeEvents_guild_id ON JokeEvents (guild_id, created_at);
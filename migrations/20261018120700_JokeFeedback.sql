@@ -0,0 +1,21 @@
+-- Add migration script here
+
+-- the message the bot sent for the joke, NULL if it only reacted
+ALTER TABLE JokeEvents ADD COLUMN reply_message_id BIGINT;
+CREATE INDEX IF NOT EXISTS JokeEvents_reply_message_id ON JokeEvents (reply_message_id);
+
+-- downvotes on messages the bot sent for a joke, per emoji so removing one of 👎 and 🗑️ keeps the other
+CREATE TABLE IF NOT EXISTS JokeFeedback (
+    message_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    emoji TEXT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    joke_name TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (message_id, user_id, emoji)
+);
+
+-- number of downvotes after which a joke message is deleted, 0 to never delete
+ALTER TABLE JokeConfig ADD COLUMN downvotes_to_delete INTEGER;
+-- ratio of downvoted messages above which the chance of a joke is lowered
+ALTER TABLE JokeConfig ADD COLUMN auto_lower_threshold FLOAT;
@@ -0,0 +1,8 @@
+-- Add migration script here
+
+-- the message that triggered the joke, to clean up the joke when it is edited or deleted
+ALTER TABLE JokeEvents ADD COLUMN trigger_message_id BIGINT;
+CREATE INDEX IF NOT EXISTS JokeEvents_trigger_message_id ON JokeEvents (trigger_message_id);
+
+-- whether edited messages are checked for jokes again
+ALTER TABLE JokeConfig ADD COLUMN evaluate_edits BOOLEAN;
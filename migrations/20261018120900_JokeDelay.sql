@@ -0,0 +1,5 @@
+-- Add migration script here
+
+-- seconds to show the typing indicator before a joke, a random time in between if both are set
+ALTER TABLE JokeConfig ADD COLUMN delay_min FLOAT;
+ALTER TABLE JokeConfig ADD COLUMN delay_max FLOAT;
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+-- locale of the jokes made in the guild, the guild's preferred locale if not set
+ALTER TABLE JokeConfig ADD COLUMN locale TEXT;
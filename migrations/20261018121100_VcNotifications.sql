@@ -0,0 +1,15 @@
+-- Add migration script here
+
+-- where a guild posts that a VC started, instead of or in addition to direct messages
+CREATE TABLE IF NOT EXISTS VcNotificationConfig (
+    guild_id BIGINT NOT NULL PRIMARY KEY,
+    -- channel to post in, unless a webhook is set
+    channel_id BIGINT,
+    webhook_url TEXT,
+    -- role mentioned in the posts
+    role_id BIGINT,
+    -- whether subscribed users still get a direct message
+    send_dms BOOLEAN NOT NULL DEFAULT TRUE,
+    -- whether the end of a VC is posted too
+    disconnect_message BOOLEAN NOT NULL DEFAULT FALSE
+);
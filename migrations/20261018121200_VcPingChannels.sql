@@ -0,0 +1,9 @@
+-- Add migration script here
+
+-- voice channels and categories a user on the ping list is pinged for, every channel if there are none
+CREATE TABLE IF NOT EXISTS VcPingChannels (
+    user_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL,
+    PRIMARY KEY (user_id, guild_id, channel_id)
+);
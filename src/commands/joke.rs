@@ -0,0 +1,350 @@
+use serenity::{
+    all::{
+        CommandDataOption, CommandDataOptionValue, CommandInteraction, CreateAllowedMentions,
+        CreateAttachment, CreateInteractionResponse, CreateInteractionResponseMessage, GuildId,
+    },
+    client::Context,
+};
+use tracing::error;
+
+use crate::{
+    allowed_jokes, dry_run, get_channel_rule, get_joke_config, get_joke_matcher,
+    get_joke_overrides, invalidate_guild_jokes, joke_stats, matcher::TriggerEvent,
+    user_template_context, Config, GuildJoke, State,
+};
+
+/// Separator between message variants in the `message` option.
+const MESSAGE_SEPARATOR: &str = ";;";
+
+/// Discord rejects message contents longer than this many characters.
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+pub fn get_option<'a>(
+    options: &'a [CommandDataOption],
+    name: &str,
+) -> Option<&'a CommandDataOptionValue> {
+    options
+        .iter()
+        .find(|option| option.name == name)
+        .map(|option| &option.value)
+}
+
+/// Builds a response message, attaching the text as a file if it's too long to send as content.
+pub fn long_response(text: String) -> CreateInteractionResponseMessage {
+    if text.chars().count() <= MAX_MESSAGE_LENGTH {
+        return CreateInteractionResponseMessage::new().content(text);
+    }
+    CreateInteractionResponseMessage::new()
+        .content("The response is too long for a message, see the attached file.")
+        .add_file(CreateAttachment::bytes(text.into_bytes(), "response.txt"))
+}
+
+fn parse_messages(messages: &str) -> Vec<String> {
+    messages
+        .split(MESSAGE_SEPARATOR)
+        .map(|message| message.trim().to_string())
+        .filter(|message| !message.is_empty())
+        .collect()
+}
+
+/// Compiles the joke to make sure it is valid, returning a user facing error otherwise.
+fn validate(joke: &GuildJoke) -> Result<(), String> {
+    joke.to_raw().compile().map(|_| ()).map_err(|errors| {
+        errors
+            .iter()
+            .map(|e| format!("Invalid {}: {}", e.field, e.error))
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+pub async fn handle_joke_command(ctx: &Context, command: &CommandInteraction) {
+    let guild_id = command.guild_id.unwrap();
+    let subcommand = command.data.options.first().unwrap();
+    let CommandDataOptionValue::SubCommand(options) = &subcommand.value else {
+        unreachable!("joke command without subcommand");
+    };
+
+    let message_text = match subcommand.name.as_str() {
+        "add" => add_joke(ctx, guild_id, options).await,
+        "remove" => remove_joke(ctx, guild_id, options).await,
+        "list" => list_jokes(ctx, guild_id).await,
+        "edit" => edit_joke(ctx, guild_id, options).await,
+        "stats" => joke_stats(ctx, command, options).await,
+        "test" => test_joke(ctx, command, options).await,
+        subcommand => unreachable!("Unknown subcommand: {}", subcommand),
+    };
+
+    if let Err(e) = command
+        .create_response(
+            &ctx,
+            CreateInteractionResponse::Message(
+                long_response(message_text)
+                    // a dry run shouldn't look like a joke to everyone else
+                    .ephemeral(subcommand.name == "test")
+                    // don't ping the users listed in the stats
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            ),
+        )
+        .await
+    {
+        error!("Error responding to joke command: {:?}", e);
+    }
+}
+
+async fn add_joke(ctx: &Context, guild_id: GuildId, options: &[CommandDataOption]) -> String {
+    let joke = GuildJoke {
+        guild_id: guild_id.get() as i64,
+        name: get_option(options, "name")
+            .and_then(|value| value.as_str())
+            .unwrap()
+            .to_string(),
+        regex: get_option(options, "regex")
+            .and_then(|value| value.as_str())
+            .unwrap()
+            .to_string(),
+        message: serde_json::to_string(&parse_messages(
+            get_option(options, "message")
+                .and_then(|value| value.as_str())
+                .unwrap(),
+        ))
+        .unwrap(),
+        reply: get_option(options, "reply")
+            .and_then(|value| value.as_bool())
+            .unwrap_or(false),
+        enabled: true,
+        priority: get_option(options, "priority")
+            .and_then(|value| value.as_i64())
+            .unwrap_or(0),
+    };
+
+    // joke names are unique across global and guild jokes
+    let is_global = ctx
+        .data
+        .read()
+        .await
+        .get::<Config>()
+        .unwrap()
+        .jokes
+        .iter()
+        .any(|global| global.name == joke.name);
+    if is_global {
+        return format!("There already is a global joke named `{}`", joke.name);
+    }
+    if let Err(e) = validate(&joke) {
+        return e;
+    }
+
+    let result = sqlx::query!(
+        "INSERT INTO Jokes (guild_id, name, regex, message, reply, enabled, priority) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (guild_id, name) DO NOTHING",
+        joke.guild_id,
+        joke.name,
+        joke.regex,
+        joke.message,
+        joke.reply,
+        joke.enabled,
+        joke.priority
+    )
+    .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap();
+    if result.rows_affected() == 0 {
+        return format!("A joke named `{}` already exists", joke.name);
+    }
+
+    invalidate_guild_jokes(ctx, guild_id).await;
+    format!("Added joke `{}`", joke.name)
+}
+
+async fn remove_joke(ctx: &Context, guild_id: GuildId, options: &[CommandDataOption]) -> String {
+    let gid = guild_id.get() as i64;
+    let name = get_option(options, "name")
+        .and_then(|value| value.as_str())
+        .unwrap();
+
+    let result = sqlx::query!(
+        "DELETE FROM Jokes WHERE guild_id = $1 AND name = $2",
+        gid,
+        name
+    )
+    .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap();
+    if result.rows_affected() == 0 {
+        return format!("No joke named `{}` found", name);
+    }
+
+    invalidate_guild_jokes(ctx, guild_id).await;
+    format!("Removed joke `{}`", name)
+}
+
+async fn list_jokes(ctx: &Context, guild_id: GuildId) -> String {
+    let gid = guild_id.get() as i64;
+    let mut message_text: Vec<String> = Vec::new();
+
+    let global_jokes: Vec<String> = ctx
+        .data
+        .read()
+        .await
+        .get::<Config>()
+        .unwrap()
+        .jokes
+        .iter()
+        .filter(|joke| {
+            joke.servers
+                .as_ref()
+                .is_none_or(|servers| servers.contains(&guild_id))
+        })
+        .map(|joke| format!("`{}`", joke.name))
+        .collect();
+    message_text.push(format!("Global jokes: {}", global_jokes.join(", ")));
+
+    let guild_jokes = sqlx::query_as!(
+        GuildJoke,
+        "SELECT guild_id, name, regex, message, reply, enabled, priority FROM Jokes WHERE guild_id = $1 ORDER BY name",
+        gid
+    )
+    .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap();
+    if guild_jokes.is_empty() {
+        message_text.push("No guild jokes".to_string());
+    } else {
+        message_text.push("Guild jokes:".to_string());
+        for joke in guild_jokes {
+            message_text.push(format!(
+                "- `{}`{}: `{}` ({} messages)",
+                joke.name,
+                if joke.enabled { "" } else { " (disabled)" },
+                joke.regex,
+                joke.messages().len()
+            ));
+        }
+    }
+
+    message_text.join("\n")
+}
+
+async fn edit_joke(ctx: &Context, guild_id: GuildId, options: &[CommandDataOption]) -> String {
+    let gid = guild_id.get() as i64;
+    let name = get_option(options, "name")
+        .and_then(|value| value.as_str())
+        .unwrap();
+
+    let joke = sqlx::query_as!(
+        GuildJoke,
+        "SELECT guild_id, name, regex, message, reply, enabled, priority FROM Jokes WHERE guild_id = $1 AND name = $2",
+        gid,
+        name
+    )
+    .fetch_optional(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap();
+    let Some(mut joke) = joke else {
+        return format!("No joke named `{}` found", name);
+    };
+
+    if let Some(regex) = get_option(options, "regex").and_then(|value| value.as_str()) {
+        joke.regex = regex.to_string();
+    }
+    if let Some(message) = get_option(options, "message").and_then(|value| value.as_str()) {
+        joke.message = serde_json::to_string(&parse_messages(message)).unwrap();
+    }
+    if let Some(reply) = get_option(options, "reply").and_then(|value| value.as_bool()) {
+        joke.reply = reply;
+    }
+    if let Some(enabled) = get_option(options, "enabled").and_then(|value| value.as_bool()) {
+        joke.enabled = enabled;
+    }
+    if let Some(priority) = get_option(options, "priority").and_then(|value| value.as_i64()) {
+        joke.priority = priority;
+    }
+    if let Err(e) = validate(&joke) {
+        return e;
+    }
+
+    sqlx::query!(
+        "UPDATE Jokes SET regex = $1, message = $2, reply = $3, enabled = $4, priority = $5 WHERE guild_id = $6 AND name = $7",
+        joke.regex,
+        joke.message,
+        joke.reply,
+        joke.enabled,
+        joke.priority,
+        gid,
+        name
+    )
+    .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap();
+
+    invalidate_guild_jokes(ctx, guild_id).await;
+    format!("Updated joke `{}`", name)
+}
+
+/// Runs the message through the joke pipeline of the channel without sending the joke.
+async fn test_joke(
+    ctx: &Context,
+    command: &CommandInteraction,
+    options: &[CommandDataOption],
+) -> String {
+    let guild_id = command.guild_id.unwrap();
+    let text = get_option(options, "text")
+        .and_then(|value| value.as_str())
+        .unwrap();
+
+    let max_message_length = ctx
+        .data
+        .read()
+        .await
+        .get::<Config>()
+        .unwrap()
+        .max_message_length;
+    if text.chars().count() > max_message_length {
+        return format!(
+            "Messages longer than {} characters are not checked for jokes",
+            max_message_length
+        );
+    }
+
+    let mut message_text: Vec<String> = Vec::new();
+    let config = get_joke_config(ctx, guild_id).await;
+    let channel_rule = get_channel_rule(ctx, command.channel_id).await;
+    if channel_rule.as_ref().is_some_and(|rule| !rule.enabled) {
+        message_text.push("Jokes are disabled in this channel".to_string());
+    }
+
+    let matcher = get_joke_matcher(ctx, guild_id).await;
+    let overrides = get_joke_overrides(ctx, guild_id).await;
+    let allowed = allowed_jokes(ctx, matcher.jokes(), &overrides, command.channel_id).await;
+    let context =
+        user_template_context(ctx, Some(guild_id), command.channel_id, &command.user).await;
+    let strategy = config.match_strategy();
+    // the test message can't have attachments, but it can mention the bot
+    let bot_id = ctx.cache.current_user().id;
+    let event = TriggerEvent {
+        content: text.to_string(),
+        mentions_bot: text.contains(&format!("<@{}>", bot_id))
+            || text.contains(&format!("<@!{}>", bot_id)),
+        author_roles: command
+            .member
+            .as_ref()
+            .map(|member| member.roles.clone())
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    // same as for messages, the regexes may take a while
+    let report = tokio::task::spawn_blocking(move || {
+        dry_run(
+            &matcher,
+            &event,
+            &allowed,
+            strategy,
+            &context,
+            &mut rand::thread_rng(),
+        )
+    })
+    .await
+    .unwrap();
+    message_text.push(report);
+    message_text.join("\n")
+}
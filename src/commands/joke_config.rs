@@ -1,21 +1,77 @@
 use serenity::{
-    all::{CommandInteraction, CreateInteractionResponse, CreateInteractionResponseMessage},
+    all::{CommandInteraction, CreateAutocompleteResponse, CreateInteractionResponse},
     client::Context,
 };
+use tracing::error;
 
-use crate::{JokeConfig, State};
+use crate::{
+    get_guild_locale, get_joke_config, get_joke_matcher, get_joke_overrides, get_option,
+    invalidate_guild_jokes, is_valid_locale, long_response, JokeChannelConfig, JokeOverride, State,
+    DEFAULT_DOWNVOTES_TO_DELETE, DEFAULT_LOCALE,
+};
 
 pub async fn handle_joke_config_command(ctx: &Context, command: &CommandInteraction) {
     let guild_id = command.guild_id.unwrap().get() as i64;
+    let options = &command.data.options;
     // get command options
-    let chance_option = command
-        .data
-        .options
-        .iter()
-        .find(|option| option.name == "chance")
-        .and_then(|option| option.clone().value.as_i64());
+    let chance_option = get_option(options, "chance").and_then(|value| value.as_i64());
+    let joke_option = get_option(options, "joke").and_then(|value| value.as_str());
+    let enabled_option = get_option(options, "enabled").and_then(|value| value.as_bool());
+    let joke_chance_option = get_option(options, "joke-chance").and_then(|value| value.as_i64());
+    let channel_option = get_option(options, "channel").and_then(|value| value.as_channel_id());
+    let reset_option = get_option(options, "reset")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    let joke_cooldown_option =
+        get_option(options, "joke-cooldown").and_then(|value| value.as_i64());
+    let user_cooldown_option =
+        get_option(options, "user-cooldown").and_then(|value| value.as_i64());
+    let guild_cooldown_option =
+        get_option(options, "server-cooldown").and_then(|value| value.as_i64());
+    let match_strategy_option =
+        get_option(options, "match-strategy").and_then(|value| value.as_str());
+    let max_matches_option = get_option(options, "max-matches").and_then(|value| value.as_i64());
+    let downvotes_to_delete_option =
+        get_option(options, "downvotes-to-delete").and_then(|value| value.as_i64());
+    let auto_lower_threshold_option = get_option(options, "auto-lower-threshold")
+        .and_then(|value| value.as_i64())
+        .map(|threshold| threshold as f64 / 100.0);
+    let evaluate_edits_option =
+        get_option(options, "evaluate-edits").and_then(|value| value.as_bool());
+    let delay_min_option = get_option(options, "delay-min").and_then(|value| value.as_f64());
+    let delay_max_option = get_option(options, "delay-max").and_then(|value| value.as_f64());
+    let locale_option = get_option(options, "locale").and_then(|value| value.as_str());
     let mut message_text: Vec<String> = Vec::new();
-    if let Some(chance) = chance_option {
+    // make sure the guild has a config row, so the updates below apply
+    get_joke_config(ctx, command.guild_id.unwrap()).await;
+    if let (Some(_), Some(_)) = (channel_option, joke_option) {
+        message_text.push("A joke and a channel can't be configured at once".to_string());
+    } else if let Some(channel_id) = channel_option {
+        // `chance` and `enabled` apply to the channel instead of the guild
+        let channel_id = channel_id.get() as i64;
+        if reset_option {
+            sqlx::query!(
+                "DELETE FROM JokeChannelConfig WHERE channel_id = $1",
+                channel_id
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+        } else {
+            let chance = chance_option.map(|chance| chance as f64 / 100.0);
+            sqlx::query!(
+                "INSERT INTO JokeChannelConfig (channel_id, guild_id, enabled, chance) VALUES ($1, $2, COALESCE($3, TRUE), $4)
+                ON CONFLICT (channel_id) DO UPDATE SET enabled = COALESCE($3, enabled), chance = COALESCE($4, chance)",
+                channel_id,
+                guild_id,
+                enabled_option,
+                chance
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+        }
+    } else if let Some(chance) = chance_option {
         // get guild id
         let chance = chance as f64 / 100.0;
         // update or insert chance
@@ -29,28 +85,251 @@ pub async fn handle_joke_config_command(ctx: &Context, command: &CommandInteract
         .unwrap();
     }
 
+    if let (Some(joke_name), None) = (joke_option, channel_option) {
+        let matcher = get_joke_matcher(ctx, command.guild_id.unwrap()).await;
+        if !matcher.jokes().iter().any(|joke| joke.name == joke_name) {
+            message_text.push(format!("No joke named `{}` found", joke_name));
+        } else if reset_option {
+            sqlx::query!(
+                "DELETE FROM JokeOverride WHERE guild_id = $1 AND joke_name = $2",
+                guild_id,
+                joke_name
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+        } else {
+            let joke_chance = joke_chance_option.map(|chance| chance as f64 / 100.0);
+            // only overwrite the settings that were given
+            sqlx::query!(
+                "INSERT INTO JokeOverride (guild_id, joke_name, enabled, chance) VALUES ($1, $2, COALESCE($3, TRUE), $4)
+                ON CONFLICT (guild_id, joke_name) DO UPDATE SET enabled = COALESCE($3, enabled), chance = COALESCE($4, chance)",
+                guild_id,
+                joke_name,
+                enabled_option,
+                joke_chance
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+        }
+    }
+
+    if joke_cooldown_option.is_some()
+        || user_cooldown_option.is_some()
+        || guild_cooldown_option.is_some()
+    {
+        // only overwrite the cooldowns that were given
+        sqlx::query!(
+            "UPDATE JokeConfig SET joke_cooldown = COALESCE($1, joke_cooldown), user_cooldown = COALESCE($2, user_cooldown), guild_cooldown = COALESCE($3, guild_cooldown) WHERE guild_id = $4",
+            joke_cooldown_option,
+            user_cooldown_option,
+            guild_cooldown_option,
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+    }
+
+    if match_strategy_option.is_some() || max_matches_option.is_some() {
+        // only overwrite the settings that were given
+        sqlx::query!(
+            "UPDATE JokeConfig SET match_strategy = COALESCE($1, match_strategy), max_matches = COALESCE($2, max_matches) WHERE guild_id = $3",
+            match_strategy_option,
+            max_matches_option,
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+    }
+
+    if downvotes_to_delete_option.is_some() || auto_lower_threshold_option.is_some() {
+        sqlx::query!(
+            "UPDATE JokeConfig SET downvotes_to_delete = COALESCE($1, downvotes_to_delete), auto_lower_threshold = COALESCE($2, auto_lower_threshold) WHERE guild_id = $3",
+            downvotes_to_delete_option,
+            auto_lower_threshold_option,
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+    }
+
+    if let Some(evaluate_edits) = evaluate_edits_option {
+        sqlx::query!(
+            "UPDATE JokeConfig SET evaluate_edits = $1 WHERE guild_id = $2",
+            evaluate_edits,
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+    }
+
+    if delay_min_option.is_some() || delay_max_option.is_some() {
+        sqlx::query!(
+            "UPDATE JokeConfig SET delay_min = COALESCE($1, delay_min), delay_max = COALESCE($2, delay_max) WHERE guild_id = $3",
+            delay_min_option,
+            delay_max_option,
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+    }
+
+    if let Some(locale) = locale_option {
+        // `auto` goes back to the server's language
+        let locale = locale.trim();
+        if locale != "auto" && !is_valid_locale(locale) {
+            message_text.push(format!(
+                "`{}` is not a locale like `de` or `en-US`, or `auto`",
+                locale
+            ));
+        } else {
+            let locale = (locale != "auto").then_some(locale);
+            sqlx::query!(
+                "UPDATE JokeConfig SET locale = $1 WHERE guild_id = $2",
+                locale,
+                guild_id
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+            // the cached matcher only has the jokes of the old locale
+            invalidate_guild_jokes(ctx, command.guild_id.unwrap()).await;
+        }
+    }
+
     // get current config
-    let config = sqlx::query_as!(
-        JokeConfig,
-        "SELECT * FROM JokeConfig WHERE guild_id = $1",
+    let config = get_joke_config(ctx, command.guild_id.unwrap()).await;
+    message_text.push(format!("Chance: {}%", config.chance * 100.0));
+    message_text.push(format!(
+        "Cooldowns: {}s per joke and channel, {}s per user, {}s per server",
+        config.joke_cooldown.unwrap_or(0),
+        config.user_cooldown.unwrap_or(0),
+        config.guild_cooldown.unwrap_or(0)
+    ));
+    message_text.push(format!("Match strategy: {}", config.match_strategy()));
+    message_text.push(format!(
+        "Downvotes to delete a joke: {}",
+        config
+            .downvotes_to_delete
+            .unwrap_or(DEFAULT_DOWNVOTES_TO_DELETE)
+    ));
+    match config
+        .auto_lower_threshold
+        .filter(|threshold| *threshold > 0.0)
+    {
+        Some(threshold) => message_text.push(format!(
+            "Lower the chance of jokes downvoted more than {}% of the time",
+            threshold * 100.0
+        )),
+        None => message_text.push("Don't lower the chance of downvoted jokes".to_string()),
+    }
+
+    match config.delay() {
+        Some(delay) => message_text.push(format!("Delay before jokes: {}", delay)),
+        None => message_text.push("No delay before jokes".to_string()),
+    }
+    if config.evaluate_edits.unwrap_or(false) {
+        message_text.push("Edited messages are checked for jokes again".to_string());
+    } else {
+        message_text.push("Edited messages are not checked for jokes".to_string());
+    }
+    match (
+        &config.locale,
+        get_guild_locale(ctx, command.guild_id.unwrap()).await,
+    ) {
+        (Some(locale), _) => message_text.push(format!("Joke language: {}", locale)),
+        (None, Some(locale)) => {
+            message_text.push(format!("Joke language: {} (server language)", locale))
+        }
+        (None, None) => message_text.push(format!(
+            "Joke language: {} (the server language is not known yet)",
+            DEFAULT_LOCALE
+        )),
+    }
+
+    // the probability of responding to a message that triggers the joke
+    let overrides = get_joke_overrides(ctx, command.guild_id.unwrap()).await;
+    message_text.push("Jokes:".to_string());
+    for joke in get_joke_matcher(ctx, command.guild_id.unwrap())
+        .await
+        .jokes()
+    {
+        let joke_override = overrides.get(&joke.name);
+        let probability = match joke_override {
+            Some(joke_override) if !joke_override.enabled => "disabled".to_string(),
+            Some(JokeOverride {
+                chance: Some(chance),
+                ..
+            }) => format!("{}% (override)", chance * 100.0),
+            _ => format!("{}%", config.chance * 100.0),
+        };
+        message_text.push(format!("- `{}`: {}", joke.name, probability));
+    }
+
+    message_text.push("Channels:".to_string());
+    let channel_rules = sqlx::query_as!(
+        JokeChannelConfig,
+        "SELECT channel_id, guild_id, enabled, chance FROM JokeChannelConfig WHERE guild_id = $1",
         guild_id
     )
-    .fetch_one(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
     .await
-    .ok();
-    if let Some(config) = config {
-        message_text.push(format!("Chance: {}%", config.chance * 100.0));
-    } else {
-        message_text.push("No config found".to_string());
+    .unwrap();
+    for rule in channel_rules {
+        let chance = rule
+            .chance
+            .map(|chance| format!(", chance: {}%", chance * 100.0))
+            .unwrap_or_default();
+        message_text.push(format!(
+            "- <#{}>: {}{}",
+            rule.channel_id,
+            if rule.enabled { "enabled" } else { "disabled" },
+            chance
+        ));
     }
 
-    command
+    if let Err(e) = command
         .create_response(
             &ctx,
-            CreateInteractionResponse::Message(
-                CreateInteractionResponseMessage::new().content(message_text.join("\n")),
-            ),
+            CreateInteractionResponse::Message(long_response(message_text.join("\n"))),
         )
         .await
+    {
+        error!("Error responding to joke-config command: {:?}", e);
+    }
+}
+
+/// Suggests the names of the jokes available in the guild for the `joke` option.
+pub async fn handle_joke_config_autocomplete(ctx: &Context, command: &CommandInteraction) {
+    let Some(guild_id) = command.guild_id else {
+        return;
+    };
+    let typed = command
+        .data
+        .autocomplete()
+        .map(|option| option.value.to_lowercase())
+        .unwrap_or_default();
+
+    let mut response = CreateAutocompleteResponse::new();
+    for joke in get_joke_matcher(ctx, guild_id)
+        .await
+        .jokes()
+        .iter()
+        .filter(|joke| joke.name.to_lowercase().contains(&typed))
+        // discord allows at most 25 choices
+        .take(25)
+    {
+        response = response.add_string_choice(&joke.name, &joke.name);
+    }
+
+    command
+        .create_response(&ctx, CreateInteractionResponse::Autocomplete(response))
+        .await
         .unwrap();
 }
@@ -0,0 +1,80 @@
+use serenity::all::{CommandDataOption, CommandInteraction};
+use serenity::client::Context;
+
+use crate::{get_option, is_owner, State};
+
+/// Number of entries shown in each top list.
+const TOP_LIMIT: i64 = 10;
+/// Number of days shown in the per day counts.
+const DAYS: i64 = 14;
+
+/// Builds the statistics for `/joke stats`, for the guild or for every guild with `global`.
+pub async fn joke_stats(
+    ctx: &Context,
+    command: &CommandInteraction,
+    options: &[CommandDataOption],
+) -> String {
+    let global = get_option(options, "global")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    if global && !is_owner(command.user.id) {
+        return "Only the owner of the bot can see global statistics".to_string();
+    }
+    // NULL matches every guild
+    let guild_id = if global {
+        None
+    } else {
+        Some(command.guild_id.unwrap().get() as i64)
+    };
+    let pool = ctx.data.read().await.get::<State>().unwrap().pool.clone();
+
+    let top_jokes = sqlx::query!(
+        r#"SELECT joke_name, COUNT(*) AS "count!: i64" FROM JokeEvents WHERE ($1 IS NULL OR guild_id = $1)
+        GROUP BY joke_name ORDER BY 2 DESC LIMIT $2"#,
+        guild_id,
+        TOP_LIMIT
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    let top_victims = sqlx::query!(
+        r#"SELECT user_id, COUNT(*) AS "count!: i64" FROM JokeEvents WHERE ($1 IS NULL OR guild_id = $1)
+        GROUP BY user_id ORDER BY 2 DESC LIMIT $2"#,
+        guild_id,
+        TOP_LIMIT
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    // today counts as one of the days, so the first one is midnight `DAYS - 1` days ago
+    let days_ago = format!("-{} days", DAYS - 1);
+    let per_day = sqlx::query!(
+        r#"SELECT date(created_at) AS "day!: String", COUNT(*) AS "count!: i64" FROM JokeEvents
+        WHERE ($1 IS NULL OR guild_id = $1) AND created_at >= date('now', $2)
+        GROUP BY 1 ORDER BY 1"#,
+        guild_id,
+        days_ago
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+
+    if top_jokes.is_empty() {
+        return "No jokes made yet".to_string();
+    }
+
+    let mut message_text: Vec<String> = Vec::new();
+    message_text.push("**Top jokes**".to_string());
+    for row in top_jokes {
+        message_text.push(format!("- `{}`: {}", row.joke_name, row.count));
+    }
+    message_text.push("**Top victims**".to_string());
+    for row in top_victims {
+        message_text.push(format!("- <@{}>: {}", row.user_id, row.count));
+    }
+    message_text.push(format!("**Jokes in the last {} days**", DAYS));
+    for row in per_day {
+        message_text.push(format!("- {}: {}", row.day, row.count));
+    }
+    message_text.join("\n")
+}
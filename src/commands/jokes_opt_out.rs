@@ -0,0 +1,75 @@
+use serenity::{
+    all::{
+        CommandDataOptionValue, CommandInteraction, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    client::Context,
+};
+
+use crate::{get_option, State};
+
+/// `guild_id` used in `JokeOptOut` for opt-outs that apply to every guild.
+pub const GLOBAL_OPT_OUT: i64 = 0;
+
+pub async fn handle_jokes_command(ctx: &Context, command: &CommandInteraction) {
+    let user_id = command.user.id.get() as i64;
+    let subcommand = command.data.options.first().unwrap();
+    let CommandDataOptionValue::SubCommand(options) = &subcommand.value else {
+        unreachable!("jokes command without subcommand");
+    };
+    let global = get_option(options, "global")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    // outside of a guild, the opt-out can only be global
+    let guild_id = match command.guild_id {
+        Some(guild_id) if !global => guild_id.get() as i64,
+        _ => GLOBAL_OPT_OUT,
+    };
+    let scope = if guild_id == GLOBAL_OPT_OUT {
+        "in any server"
+    } else {
+        "in this server"
+    };
+
+    let message_text = match subcommand.name.as_str() {
+        "opt-out" => {
+            sqlx::query!(
+                "INSERT INTO JokeOptOut (user_id, guild_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                user_id,
+                guild_id
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+            format!(
+                "The bot won't make jokes about your messages {} anymore",
+                scope
+            )
+        }
+        "opt-in" => {
+            sqlx::query!(
+                "DELETE FROM JokeOptOut WHERE user_id = $1 AND guild_id = $2",
+                user_id,
+                guild_id
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+            format!("The bot can make jokes about your messages {} again", scope)
+        }
+        subcommand => unreachable!("Unknown subcommand: {}", subcommand),
+    };
+
+    command
+        .create_response(
+            &ctx,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(message_text)
+                    // a personal preference, nobody else needs to see it
+                    .ephemeral(true),
+            ),
+        )
+        .await
+        .unwrap();
+}
@@ -1,5 +1,13 @@
-pub mod vcping;
+pub mod joke;
 pub mod joke_config;
+pub mod joke_stats;
+pub mod jokes_opt_out;
+pub mod vcping;
+pub mod vcping_config;
 
+pub use joke::*;
+pub use joke_config::*;
+pub use joke_stats::*;
+pub use jokes_opt_out::*;
 pub use vcping::*;
-pub use joke_config::*;
\ No newline at end of file
+pub use vcping_config::*;
@@ -3,7 +3,7 @@ use serenity::{
     client::Context,
 };
 
-use crate::{State, UserIDGuildID};
+use crate::{get_option, State, UserIDGuildID};
 
 pub async fn handle_vcping_command(ctx: &Context, command: &CommandInteraction) {
     let user_id = command.member.as_ref().unwrap().user.id.get() as i64;
@@ -14,6 +14,9 @@ pub async fn handle_vcping_command(ctx: &Context, command: &CommandInteraction)
         .iter()
         .find(|option| option.name == "disconnect-message")
         .and_then(|option| option.clone().value.as_bool());
+    let channel_option = get_option(&command.data.options, "channel")
+        .and_then(|value| value.as_channel_id())
+        .map(|channel_id| channel_id.get() as i64);
     let guild_id = command.guild_id.unwrap().get() as i64;
     // let guild = guild_id.to_partial_guild(&ctx.http).await.unwrap();
     let user_id_exists: Option<UserIDGuildID> = sqlx::query_as!(
@@ -28,7 +31,9 @@ pub async fn handle_vcping_command(ctx: &Context, command: &CommandInteraction)
 
     // add user to map, if they are not already in it
     // remove user from map, if they are already in it
-    let message_text = if user_id_exists.is_none() {
+    let mut message_text: Vec<String> = Vec::new();
+    let mut on_ping_list = true;
+    if user_id_exists.is_none() {
         sqlx::query!(
             "INSERT INTO UserIDGuildID (user_id, guild_id, disconnect_message) VALUES ($1, $2, $3)",
             user_id,
@@ -38,9 +43,20 @@ pub async fn handle_vcping_command(ctx: &Context, command: &CommandInteraction)
         .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
         .await
         .unwrap();
+        if let Some(channel_id) = channel_option {
+            sqlx::query!(
+                "INSERT INTO VcPingChannels (user_id, guild_id, channel_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+                user_id,
+                guild_id,
+                channel_id
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+        }
 
-        "You have been added to the ping list!"
-    } else {
+        message_text.push("You have been added to the ping list!".to_string());
+    } else if disconnect_message.is_some() || channel_option.is_some() {
         // check, if the user wants to change the disconnect message setting
         if let Some(disconnect_message) = disconnect_message {
             sqlx::query!(
@@ -52,27 +68,113 @@ pub async fn handle_vcping_command(ctx: &Context, command: &CommandInteraction)
             .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
             .await
             .unwrap();
-            "Your disconnect message setting has been updated!"
-        } else {
-            // remove user from map
-            sqlx::query!(
-                "DELETE FROM UserIDGuildID WHERE user_id = $1 AND guild_id = $2",
+            message_text.push("Your disconnect message setting has been updated!".to_string());
+        }
+        // a channel the user is already pinged for is removed again
+        if let Some(channel_id) = channel_option {
+            let removed = sqlx::query!(
+                "DELETE FROM VcPingChannels WHERE user_id = $1 AND guild_id = $2 AND channel_id = $3",
                 user_id,
-                guild_id
+                guild_id,
+                channel_id
             )
             .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
             .await
-            .unwrap();
-            // send message to user
-            "You have been removed from the ping list!"
+            .unwrap()
+            .rows_affected()
+                > 0;
+            if removed {
+                message_text.push(format!(
+                    "You will no longer be pinged for VCs in <#{}>!",
+                    channel_id
+                ));
+                // without any channels left the user would be pinged everywhere, so remove them instead
+                let remaining = sqlx::query!(
+                    "SELECT COUNT(*) AS count FROM VcPingChannels WHERE user_id = $1 AND guild_id = $2",
+                    user_id,
+                    guild_id
+                )
+                .fetch_one(&ctx.data.read().await.get::<State>().unwrap().pool)
+                .await
+                .unwrap()
+                .count;
+                if remaining == 0 {
+                    sqlx::query!(
+                        "DELETE FROM UserIDGuildID WHERE user_id = $1 AND guild_id = $2",
+                        user_id,
+                        guild_id
+                    )
+                    .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+                    .await
+                    .unwrap();
+                    on_ping_list = false;
+                    message_text.push(
+                        "That was your last channel, so you have been removed from the ping list!"
+                            .to_string(),
+                    );
+                }
+            } else {
+                sqlx::query!(
+                    "INSERT INTO VcPingChannels (user_id, guild_id, channel_id) VALUES ($1, $2, $3)",
+                    user_id,
+                    guild_id,
+                    channel_id
+                )
+                .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+                .await
+                .unwrap();
+                message_text.push(format!("You will be pinged for VCs in <#{}>!", channel_id));
+            }
+        }
+    } else {
+        // remove user from map
+        sqlx::query!(
+            "DELETE FROM UserIDGuildID WHERE user_id = $1 AND guild_id = $2",
+            user_id,
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "DELETE FROM VcPingChannels WHERE user_id = $1 AND guild_id = $2",
+            user_id,
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+        on_ping_list = false;
+        // send message to user
+        message_text.push("You have been removed from the ping list!".to_string());
+    }
+
+    // tell the user which channels they are pinged for, if they are still on the list
+    if on_ping_list {
+        let channels = sqlx::query!(
+            "SELECT channel_id FROM VcPingChannels WHERE user_id = $1 AND guild_id = $2",
+            user_id,
+            guild_id
+        )
+        .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+        if channels.is_empty() {
+            message_text.push("You are pinged for VCs in every channel".to_string());
+        } else {
+            let channels: Vec<String> = channels
+                .iter()
+                .map(|row| format!("<#{}>", row.channel_id))
+                .collect();
+            message_text.push(format!("You are pinged for VCs in {}", channels.join(", ")));
         }
-    };
+    }
 
     command
         .create_response(
             &ctx,
             CreateInteractionResponse::Message(
-                CreateInteractionResponseMessage::new().content(message_text),
+                CreateInteractionResponseMessage::new().content(message_text.join("\n")),
             ),
         )
         .await
@@ -0,0 +1,142 @@
+use serenity::{
+    all::{
+        CommandInteraction, CreateAllowedMentions, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    client::Context,
+};
+
+use crate::{get_option, get_vc_notification_config, parse_webhook_url, State};
+
+pub async fn handle_vcping_config_command(ctx: &Context, command: &CommandInteraction) {
+    let guild_id = command.guild_id.unwrap().get() as i64;
+    let options = &command.data.options;
+    // get command options
+    let channel_option = get_option(options, "channel")
+        .and_then(|value| value.as_channel_id())
+        .map(|channel_id| channel_id.get() as i64);
+    let webhook_option = get_option(options, "webhook").and_then(|value| value.as_str());
+    let role_option = get_option(options, "role")
+        .and_then(|value| value.as_role_id())
+        .map(|role_id| role_id.get() as i64);
+    let clear_role_option = get_option(options, "clear-role")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    let dms_option = get_option(options, "dms").and_then(|value| value.as_bool());
+    let disconnect_message_option =
+        get_option(options, "disconnect-message").and_then(|value| value.as_bool());
+    let reset_option = get_option(options, "reset")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    let mut message_text: Vec<String> = Vec::new();
+
+    if reset_option {
+        sqlx::query!(
+            "DELETE FROM VcNotificationConfig WHERE guild_id = $1",
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+    } else if channel_option.is_some() && webhook_option.is_some() {
+        message_text.push("A channel and a webhook can't be set at once".to_string());
+    } else {
+        // make sure the guild has a config row, so the updates below apply
+        sqlx::query!(
+            "INSERT INTO VcNotificationConfig (guild_id) VALUES ($1) ON CONFLICT (guild_id) DO NOTHING",
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+
+        // posts go either to the channel or through the webhook
+        if let Some(channel_id) = channel_option {
+            sqlx::query!(
+                "UPDATE VcNotificationConfig SET channel_id = $1, webhook_url = NULL WHERE guild_id = $2",
+                channel_id,
+                guild_id
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+        } else if let Some(webhook_url) = webhook_option {
+            if parse_webhook_url(webhook_url).is_none() {
+                message_text.push("Invalid webhook url".to_string());
+            } else {
+                sqlx::query!(
+                    "UPDATE VcNotificationConfig SET webhook_url = $1, channel_id = NULL WHERE guild_id = $2",
+                    webhook_url,
+                    guild_id
+                )
+                .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+                .await
+                .unwrap();
+            }
+        }
+
+        // only overwrite the settings that were given
+        sqlx::query!(
+            "UPDATE VcNotificationConfig SET role_id = COALESCE($1, role_id), send_dms = COALESCE($2, send_dms), disconnect_message = COALESCE($3, disconnect_message) WHERE guild_id = $4",
+            role_option,
+            dms_option,
+            disconnect_message_option,
+            guild_id
+        )
+        .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+        .await
+        .unwrap();
+        if clear_role_option {
+            sqlx::query!(
+                "UPDATE VcNotificationConfig SET role_id = NULL WHERE guild_id = $1",
+                guild_id
+            )
+            .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+            .await
+            .unwrap();
+        }
+    }
+
+    // get current config
+    match get_vc_notification_config(ctx, command.guild_id.unwrap()).await {
+        Some(config) => {
+            // the webhook url contains its token, so it isn't shown
+            if config.webhook_url.is_some() {
+                message_text.push("VC notifications are posted through a webhook".to_string());
+            } else if let Some(channel_id) = config.channel_id {
+                message_text.push(format!("VC notifications are posted in <#{}>", channel_id));
+            } else {
+                message_text.push("VC notifications are not posted".to_string());
+            }
+            if let Some(role_id) = config.role_id {
+                message_text.push(format!("Mentioned role: <@&{}>", role_id));
+            }
+            if config.disconnect_message {
+                message_text.push("The end of a VC is posted too".to_string());
+            }
+            if config.send_dms {
+                message_text.push("Users on the ping list still get a direct message".to_string());
+            } else {
+                message_text.push("Users on the ping list don't get direct messages".to_string());
+            }
+        }
+        None => message_text.push(
+            "VC notifications are not posted, users on the ping list get a direct message"
+                .to_string(),
+        ),
+    }
+
+    command
+        .create_response(
+            &ctx,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(message_text.join("\n"))
+                    // keep the settings, like where the webhook posts, between the admins
+                    .ephemeral(true)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            ),
+        )
+        .await
+        .unwrap();
+}
@@ -1,45 +1,987 @@
-//use regex::Regex;
-use fancy_regex::Regex;
+use fancy_regex::{Regex, RegexBuilder};
 
-use serde::{Deserialize, Deserializer};
-use serenity::{all::GuildId, prelude::TypeMapKey};
+use crate::template::Template;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde::Deserialize;
+use serenity::{
+    all::{GuildId, ReactionType, RoleId, StickerId},
+    prelude::TypeMapKey,
+};
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+/// Backtrack limit of joke regexes that don't set their own, same as the fancy_regex default.
+pub const DEFAULT_BACKTRACK_LIMIT: usize = 1_000_000;
+/// Messages longer than this, in characters, are not checked for jokes.
+pub const DEFAULT_MAX_MESSAGE_LENGTH: usize = 1000;
+/// Longest delay before a joke, in seconds, so the typing indicator stays believable.
+pub const MAX_DELAY: f64 = 60.0;
+/// Seconds a conversation waits for the next message, if the joke doesn't set it.
+pub const DEFAULT_CONVERSATION_TIMEOUT: f64 = 60.0;
+/// Longest time a conversation waits for the next message, in seconds.
+pub const MAX_CONVERSATION_TIMEOUT: f64 = 3600.0;
+/// Locale of the config jokes that don't set one, and of guilds without a known locale.
+pub const DEFAULT_LOCALE: &str = "en";
+/// Locale of jokes that work in every language, like emoji reactions.
+pub const ANY_LOCALE: &str = "*";
+
+/// Whether the locale looks like a discord locale, e.g. `de` or `en-US`.
+pub fn is_valid_locale(locale: &str) -> bool {
+    let mut parts = locale.split('-');
+    let language = parts.next().unwrap_or_default();
+    let region = parts.next();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && region.is_none_or(|region| {
+            (2..=3).contains(&region.len()) && region.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+        && parts.next().is_none()
+}
 
-#[derive(Deserialize, Clone)]
+#[derive(Clone)]
 pub struct Config {
     pub invite: String,
     pub jokes: Vec<Joke>,
+    pub max_message_length: usize,
+    pub direct_messages: DirectMessages,
+}
+
+/// Jokes about direct messages to the bot, which have no guild to configure them.
+#[derive(Clone, Default)]
+pub struct DirectMessages {
+    pub enabled: bool,
+    /// probability of responding to a message that triggers a joke
+    pub chance: f64,
+    /// separate from the guild jokes, sorted by priority
+    pub jokes: Vec<Joke>,
 }
 
 impl TypeMapKey for Config {
     type Value = Config;
 }
 
-fn regex_from_str<'de, D>(deserializer: D) -> Result<Regex, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let re: String = Deserialize::deserialize(deserializer).expect("could not parse regex");
-    Ok(fancy_regex::Regex::new(&re).unwrap())
+#[derive(Clone, Debug)]
+pub struct Joke {
+    pub name: String,
+    pub servers: Option<Vec<GuildId>>,
+    pub trigger: Trigger,
+    pub message: Vec<JokeMessage>,
+    pub reply: Option<bool>,
+    pub response: JokeResponse,
+    /// jokes with a higher priority are checked first
+    pub priority: i64,
+    /// time to show the typing indicator before responding, replaces the guild delay
+    pub delay: Option<Delay>,
+    /// follow-up messages the author can send after the joke
+    pub conversation: Option<Conversation>,
+    /// language of the joke, `None` for jokes that work in every language
+    pub locale: Option<String>,
+}
+
+/// A joke that goes on over several messages, like a knock-knock joke.
+#[derive(Clone, Debug)]
+pub struct Conversation {
+    /// how long to wait for the next message of the author
+    pub timeout: Duration,
+    pub steps: Vec<ConversationStep>,
+}
+
+/// The bot's answer to the next message of the author in a conversation.
+#[derive(Clone, Debug)]
+pub struct ConversationStep {
+    /// the next message has to match this, otherwise the conversation ends
+    pub regex: Regex,
+    pub message: Vec<JokeMessage>,
+}
+
+/// What makes a joke respond.
+#[derive(Clone, Debug)]
+pub enum Trigger {
+    /// the regex matches the content of the message
+    Regex(Regex),
+    /// the content contains one of the keywords, checked with a case insensitive regex
+    /// whose first capture group is the keyword
+    Keywords { keywords: Vec<String>, regex: Regex },
+    /// the message mentions the bot
+    Mention,
+    /// the message has an attachment whose content type starts with this, e.g. `image/`
+    Attachment(String),
+    /// the reaction is added to the message
+    Reaction(ReactionType),
+    /// the author of the message has the role
+    Role(RoleId),
+}
+
+impl Trigger {
+    /// The regex that is run over the content of the message, if the trigger has one.
+    pub fn regex(&self) -> Option<&Regex> {
+        match self {
+            Trigger::Regex(regex) | Trigger::Keywords { regex, .. } => Some(regex),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Regex(regex) => write!(f, "regex `{}`", regex.as_str()),
+            Trigger::Keywords { keywords, .. } => write!(f, "keywords `{}`", keywords.join("`, `")),
+            Trigger::Mention => write!(f, "mention of the bot"),
+            Trigger::Attachment(content_type) if content_type.is_empty() => {
+                write!(f, "any attachment")
+            }
+            Trigger::Attachment(content_type) => write!(f, "attachment of type `{}`", content_type),
+            Trigger::Reaction(reaction) => write!(f, "reaction {}", reaction),
+            Trigger::Role(role_id) => write!(f, "author with role <@&{}>", role_id),
+        }
+    }
+}
+
+impl Joke {
+    /// Picks one of the messages by weight, if the joke responds with text.
+    pub fn choose_message(&self, rng: &mut impl Rng) -> Option<usize> {
+        if !self.response.text {
+            return None;
+        }
+        WeightedIndex::new(self.message.iter().map(|message| message.weight))
+            .ok()
+            .map(|weights| weights.sample(rng))
+    }
+
+    /// Whether the joke can be made in a guild with the locale.
+    /// A joke for a language, like `en`, fits every region of it, like `en-US` and `en-GB`.
+    pub fn is_for_locale(&self, locale: &str) -> bool {
+        let Some(joke_locale) = &self.locale else {
+            return true;
+        };
+        let locale = locale.to_lowercase();
+        let joke_locale = joke_locale.to_lowercase();
+        locale == joke_locale
+            || locale
+                .strip_prefix(&joke_locale)
+                .is_some_and(|region| region.starts_with('-'))
+    }
+}
+
+/// A fixed or random time to wait before responding, in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Delay {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Delay {
+    /// Builds the delay of a guild from its settings, `None` if it doesn't wait.
+    /// Only one of the bounds being set means a fixed delay.
+    pub fn from_config(min: Option<f64>, max: Option<f64>) -> Option<Delay> {
+        let (min, max) = match (min, max) {
+            (Some(min), Some(max)) => (min.min(max), min.max(max)),
+            (Some(delay), None) | (None, Some(delay)) => (delay, delay),
+            (None, None) => return None,
+        };
+        (max > 0.0).then_some(Delay { min, max })
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        let seconds = if self.min < self.max {
+            rng.gen_range(self.min..=self.max)
+        } else {
+            self.min
+        };
+        Duration::from_secs_f64(seconds)
+    }
+}
+
+impl fmt::Display for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}s", self.min)
+        } else {
+            write!(f, "{}-{}s", self.min, self.max)
+        }
+    }
+}
+
+/// How the bot responds when a joke matches.
+#[derive(Clone, Debug)]
+pub struct JokeResponse {
+    /// send the chosen message, as text or as the description of the embed
+    pub text: bool,
+    /// reactions added to the triggering message
+    pub reactions: Vec<ReactionType>,
+    pub embed: Option<JokeEmbed>,
+    /// file in the data directory sent along with the message
+    pub attachment: Option<PathBuf>,
+    /// a sticker of the guild or a standard sticker, sent along with the message
+    pub sticker: Option<StickerId>,
+}
+
+impl JokeResponse {
+    /// Whether the response includes a message, instead of only reactions.
+    pub fn sends_message(&self) -> bool {
+        self.text || self.embed.is_some() || self.attachment.is_some() || self.sticker.is_some()
+    }
 }
 
 #[derive(Deserialize, Clone, Debug)]
-pub struct Joke {
+pub struct JokeEmbed {
+    pub title: Option<String>,
+    /// url of an image shown in the embed
+    pub image: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct RawResponse {
+    pub text: Option<bool>,
+    #[serde(default)]
+    pub reactions: Vec<String>,
+    pub embed: Option<JokeEmbed>,
+    pub attachment: Option<String>,
+    pub sticker: Option<StickerId>,
+}
+
+#[derive(Clone, Debug)]
+pub struct JokeMessage {
+    pub template: Template,
+    /// relative probability of picking this message
+    pub weight: f64,
+}
+
+/// A message variant, either just the text or the text with a weight.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum RawMessage {
+    Plain(String),
+    Weighted { text: String, weight: f64 },
+}
+
+impl RawMessage {
+    pub fn text(&self) -> &str {
+        match self {
+            RawMessage::Plain(text) => text,
+            RawMessage::Weighted { text, .. } => text,
+        }
+    }
+
+    pub fn weight(&self) -> f64 {
+        match self {
+            RawMessage::Plain(_) => 1.0,
+            RawMessage::Weighted { weight, .. } => *weight,
+        }
+    }
+}
+
+/// A delay, either a fixed number of seconds or a range to pick from.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(untagged)]
+pub enum RawDelay {
+    Fixed(f64),
+    Range { min: f64, max: f64 },
+}
+
+impl RawDelay {
+    fn compile(self) -> Result<Delay, String> {
+        let delay = match self {
+            RawDelay::Fixed(delay) => Delay {
+                min: delay,
+                max: delay,
+            },
+            RawDelay::Range { min, max } => Delay { min, max },
+        };
+        if !(delay.min.is_finite() && delay.max.is_finite() && delay.min >= 0.0) {
+            return Err("must not be negative".to_string());
+        }
+        if delay.min > delay.max {
+            return Err("`min` must not be greater than `max`".to_string());
+        }
+        if delay.max > MAX_DELAY {
+            return Err(format!("must be at most {} seconds", MAX_DELAY));
+        }
+        Ok(delay)
+    }
+}
+
+impl From<String> for RawMessage {
+    fn from(text: String) -> Self {
+        RawMessage::Plain(text)
+    }
+}
+
+/// The config as it is written in `config.json`, before any regex is compiled.
+#[derive(Deserialize)]
+struct RawConfig {
+    invite: String,
+    jokes: Vec<RawJoke>,
+    max_message_length: Option<usize>,
+    #[serde(default)]
+    direct_messages: RawDirectMessages,
+    /// locale of the jokes that don't set one, `DEFAULT_LOCALE` if not set
+    locale: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct RawDirectMessages {
+    #[serde(default)]
+    enabled: bool,
+    chance: Option<f64>,
+    #[serde(default)]
+    jokes: Vec<RawJoke>,
+}
+
+/// A trigger as it is written in `config.json`, e.g. `{ "type": "mention" }`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RawTrigger {
+    Regex {
+        regex: String,
+    },
+    Keywords {
+        keywords: Vec<String>,
+    },
+    Mention,
+    Attachment {
+        /// any attachment if not set
+        #[serde(default)]
+        content_type: String,
+    },
+    Reaction {
+        emoji: String,
+    },
+    Role {
+        role: RoleId,
+    },
+}
+
+/// Parses an emoji, allowing shortcodes like `car` in addition to the emoji itself.
+fn parse_reaction(reaction: &str) -> Result<ReactionType, String> {
+    let emoji = emojis::get_by_shortcode(reaction.trim_matches(':'))
+        .map(|emoji| emoji.as_str())
+        .unwrap_or(reaction);
+    ReactionType::try_from(emoji).map_err(|e| format!("`{}`: {}", reaction, e))
+}
+
+impl RawTrigger {
+    fn compile(self, backtrack_limit: usize) -> Result<Trigger, String> {
+        let build = |regex: &str| {
+            RegexBuilder::new(regex)
+                .backtrack_limit(backtrack_limit)
+                .build()
+                .map_err(|e| e.to_string())
+        };
+        match self {
+            RawTrigger::Regex { regex } => build(&regex).map(Trigger::Regex),
+            RawTrigger::Keywords { keywords } => {
+                let keywords: Vec<String> = keywords
+                    .iter()
+                    .map(|keyword| keyword.trim().to_string())
+                    .filter(|keyword| !keyword.is_empty())
+                    .collect();
+                if keywords.is_empty() {
+                    return Err("needs at least one keyword".to_string());
+                }
+                let escaped: Vec<String> = keywords
+                    .iter()
+                    .map(|keyword| regex::escape(keyword))
+                    .collect();
+                // keywords only match whole words
+                let regex = build(&format!(r"(?i)(?:^|\W)({})(?:\W|$)", escaped.join("|")))?;
+                Ok(Trigger::Keywords { keywords, regex })
+            }
+            RawTrigger::Mention => Ok(Trigger::Mention),
+            RawTrigger::Attachment { content_type } => {
+                Ok(Trigger::Attachment(content_type.to_lowercase()))
+            }
+            RawTrigger::Reaction { emoji } => parse_reaction(&emoji).map(Trigger::Reaction),
+            RawTrigger::Role { role } => Ok(Trigger::Role(role)),
+        }
+    }
+}
+
+/// A joke as it is written in `config.json` or stored in the database.
+#[derive(Deserialize, Default)]
+pub struct RawJoke {
     pub name: String,
     pub servers: Option<Vec<GuildId>>,
-    #[serde(deserialize_with = "regex_from_str")]
-    pub regex: Regex,
-    pub message: Vec<String>,
+    /// shorthand for a regex trigger
+    pub regex: Option<String>,
+    pub trigger: Option<RawTrigger>,
+    pub message: Vec<RawMessage>,
     pub reply: Option<bool>,
+    #[serde(default)]
+    pub response: RawResponse,
+    #[serde(default)]
+    pub priority: i64,
+    /// limits how much the regex may backtrack on a single message
+    pub backtrack_limit: Option<usize>,
+    pub delay: Option<RawDelay>,
+    pub conversation: Option<RawConversation>,
+    /// e.g. `de` or `en-US`, the jokes of guilds in other languages don't include it,
+    /// `ANY_LOCALE` for jokes that work in every language
+    pub locale: Option<String>,
 }
 
-pub fn load_config() -> Config {
-    let file_path = "config.json".to_owned();
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawConversation {
+    /// seconds, `DEFAULT_CONVERSATION_TIMEOUT` if not set
+    pub timeout: Option<f64>,
+    pub steps: Vec<RawConversationStep>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawConversationStep {
+    pub regex: String,
+    pub message: Vec<RawMessage>,
+}
+
+/// A single problem with one joke in the config.
+#[derive(Debug)]
+pub struct JokeError {
+    pub joke: String,
+    pub field: &'static str,
+    pub error: String,
+}
+
+impl fmt::Display for JokeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "joke `{}`, field `{}`: {}",
+            self.joke, self.field, self.error
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The file parsed, but one or more jokes are invalid.
+    Invalid(Vec<JokeError>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read config: {}", e),
+            ConfigError::Json(e) => write!(f, "could not parse config: {}", e),
+            ConfigError::Invalid(errors) => {
+                write!(f, "config has {} problem(s):", errors.len())?;
+                for error in errors {
+                    write!(f, "\n- {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Json(e)
+    }
+}
+
+/// Directory that attachments of jokes are loaded from.
+pub const DATA_DIRECTORY: &str = "data";
+
+impl RawResponse {
+    fn compile(self, joke: &str, errors: &mut Vec<JokeError>) -> JokeResponse {
+        let mut reactions = Vec::with_capacity(self.reactions.len());
+        for reaction in self.reactions {
+            match parse_reaction(&reaction) {
+                Ok(reaction) => reactions.push(reaction),
+                Err(e) => errors.push(JokeError {
+                    joke: joke.to_string(),
+                    field: "response.reactions",
+                    error: e,
+                }),
+            }
+        }
+
+        let attachment = self.attachment.and_then(|attachment| {
+            let path = Path::new(&attachment);
+            // attachments must stay inside the data directory
+            if !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                errors.push(JokeError {
+                    joke: joke.to_string(),
+                    field: "response.attachment",
+                    error: format!("`{}` must be a relative path without `..`", attachment),
+                });
+                return None;
+            }
+            let path = Path::new(DATA_DIRECTORY).join(path);
+            if !path.is_file() {
+                errors.push(JokeError {
+                    joke: joke.to_string(),
+                    field: "response.attachment",
+                    error: format!("`{}` does not exist", path.display()),
+                });
+                return None;
+            }
+            Some(path)
+        });
+
+        let text = self.text.unwrap_or(true);
+        // discord rejects embeds without any content
+        if let Some(embed) = &self.embed {
+            let has_title = embed.title.as_ref().is_some_and(|title| !title.is_empty());
+            let has_image = embed.image.as_ref().is_some_and(|image| !image.is_empty());
+            if !(text || has_title || has_image) {
+                errors.push(JokeError {
+                    joke: joke.to_string(),
+                    field: "response.embed",
+                    error: "needs a `title` or an `image` if `text` is false".to_string(),
+                });
+            }
+        }
+
+        JokeResponse {
+            text,
+            reactions,
+            embed: self.embed,
+            attachment,
+            sticker: self.sticker,
+        }
+    }
+}
+
+/// Compiles the message variants of a joke, `check` makes sure the captures they use exist.
+fn compile_messages(
+    joke: &str,
+    field: &'static str,
+    raw_messages: &[RawMessage],
+    check: impl Fn(&Template) -> Result<(), String>,
+    errors: &mut Vec<JokeError>,
+) -> Vec<JokeMessage> {
+    let mut messages = Vec::with_capacity(raw_messages.len());
+    for (i, message) in raw_messages.iter().enumerate() {
+        if !(message.weight().is_finite() && message.weight() > 0.0) {
+            errors.push(JokeError {
+                joke: joke.to_string(),
+                field,
+                error: format!("message {}: weight must be a positive number", i + 1),
+            });
+        }
+        let template = Template::parse(message.text())
+            .map_err(|e| e.to_string())
+            .and_then(|template| check(&template).map(|_| template));
+        match template {
+            Ok(template) => messages.push(JokeMessage {
+                template,
+                weight: message.weight(),
+            }),
+            Err(e) => errors.push(JokeError {
+                joke: joke.to_string(),
+                field,
+                error: format!("message {}: {}", i + 1, e),
+            }),
+        }
+    }
+    messages
+}
+
+impl RawJoke {
+    /// Compiles the joke, collecting every problem with it.
+    pub fn compile(self) -> Result<Joke, Vec<JokeError>> {
+        let mut errors = Vec::new();
+        let response = self.response.compile(&self.name, &mut errors);
+        if response.text && self.message.is_empty() {
+            errors.push(JokeError {
+                joke: self.name.clone(),
+                field: "message",
+                error: "must contain at least one message".to_string(),
+            });
+        }
+        let delay = self.delay.and_then(|delay| match delay.compile() {
+            Ok(delay) => Some(delay),
+            Err(e) => {
+                errors.push(JokeError {
+                    joke: self.name.clone(),
+                    field: "delay",
+                    error: e,
+                });
+                None
+            }
+        });
+        // `*` marks jokes that work in every language
+        let locale = self.locale.filter(|locale| locale != ANY_LOCALE);
+        if let Some(locale) = &locale {
+            if !is_valid_locale(locale) {
+                errors.push(JokeError {
+                    joke: self.name.clone(),
+                    field: "locale",
+                    error: format!("`{}` is not a locale like `de` or `en-US`, or `*`", locale),
+                });
+            }
+        }
+        let trigger = match (self.regex, self.trigger) {
+            (Some(regex), None) => Ok(RawTrigger::Regex { regex }),
+            (None, Some(trigger)) => Ok(trigger),
+            (Some(_), Some(_)) => Err("only one of `regex` and `trigger` can be set".to_string()),
+            (None, None) => Err("needs a `regex` or a `trigger`".to_string()),
+        }
+        .and_then(|trigger| {
+            trigger.compile(self.backtrack_limit.unwrap_or(DEFAULT_BACKTRACK_LIMIT))
+        });
+        let trigger = match trigger {
+            Ok(trigger) => Some(trigger),
+            Err(e) => {
+                errors.push(JokeError {
+                    joke: self.name.clone(),
+                    field: "trigger",
+                    error: e,
+                });
+                None
+            }
+        };
+
+        let messages = compile_messages(
+            &self.name,
+            "message",
+            &self.message,
+            |template| match &trigger {
+                Some(trigger) => match trigger.regex() {
+                    Some(regex) => template.check_captures(regex),
+                    None if template.uses_captures() => {
+                        Err("the trigger has no capture groups".to_string())
+                    }
+                    None => Ok(()),
+                },
+                None => Ok(()),
+            },
+            &mut errors,
+        );
+
+        // named captures are remembered for the rest of the conversation
+        let mut names: HashSet<String> = trigger
+            .as_ref()
+            .and_then(|trigger| trigger.regex())
+            .map(|regex| regex.capture_names().flatten().map(String::from).collect())
+            .unwrap_or_default();
+        let conversation = self.conversation.map(|conversation| {
+            let timeout = conversation.timeout.unwrap_or(DEFAULT_CONVERSATION_TIMEOUT);
+            if !(timeout > 0.0 && timeout <= MAX_CONVERSATION_TIMEOUT) {
+                errors.push(JokeError {
+                    joke: self.name.clone(),
+                    field: "conversation.timeout",
+                    error: format!(
+                        "must be a positive number of seconds, at most {}",
+                        MAX_CONVERSATION_TIMEOUT
+                    ),
+                });
+            }
+            if conversation.steps.is_empty() {
+                errors.push(JokeError {
+                    joke: self.name.clone(),
+                    field: "conversation.steps",
+                    error: "must contain at least one step".to_string(),
+                });
+            }
+            let mut steps = Vec::with_capacity(conversation.steps.len());
+            for (i, step) in conversation.steps.into_iter().enumerate() {
+                if step.message.is_empty() {
+                    errors.push(JokeError {
+                        joke: self.name.clone(),
+                        field: "conversation.steps",
+                        error: format!("step {}: must contain at least one message", i + 1),
+                    });
+                }
+                let regex = match RegexBuilder::new(&step.regex)
+                    .backtrack_limit(self.backtrack_limit.unwrap_or(DEFAULT_BACKTRACK_LIMIT))
+                    .build()
+                {
+                    Ok(regex) => regex,
+                    Err(e) => {
+                        errors.push(JokeError {
+                            joke: self.name.clone(),
+                            field: "conversation.steps",
+                            error: format!("step {}: {}", i + 1, e),
+                        });
+                        continue;
+                    }
+                };
+                let message = compile_messages(
+                    &self.name,
+                    "conversation.steps",
+                    &step.message,
+                    |template| template.check_captures_with(&regex, &names),
+                    &mut errors,
+                );
+                names.extend(regex.capture_names().flatten().map(String::from));
+                steps.push(ConversationStep { regex, message });
+            }
+            Conversation {
+                // an invalid timeout is reported above
+                timeout: Duration::try_from_secs_f64(timeout).unwrap_or_default(),
+                steps,
+            }
+        });
+
+        match trigger {
+            Some(trigger) if errors.is_empty() => Ok(Joke {
+                name: self.name,
+                servers: self.servers,
+                trigger,
+                message: messages,
+                reply: self.reply,
+                response,
+                priority: self.priority,
+                delay,
+                conversation,
+                locale,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+impl RawConfig {
+    /// Compiles every joke, collecting all problems instead of stopping at the first one.
+    fn validate(self) -> Result<Config, ConfigError> {
+        let mut errors = Vec::new();
+        let locale = self.locale.unwrap_or_else(|| DEFAULT_LOCALE.to_string());
+        if locale != ANY_LOCALE && !is_valid_locale(&locale) {
+            errors.push(JokeError {
+                joke: "config".to_string(),
+                field: "locale",
+                error: format!("`{}` is not a locale like `de` or `en-US`, or `*`", locale),
+            });
+        }
+        let mut raw_jokes = self.jokes;
+        for joke in &mut raw_jokes {
+            joke.locale.get_or_insert_with(|| locale.clone());
+        }
+        let jokes = compile_jokes(raw_jokes, &mut errors);
+        let mut dm_jokes = compile_jokes(self.direct_messages.jokes, &mut errors);
+        dm_jokes.sort_by_key(|joke| std::cmp::Reverse(joke.priority));
+        let dm_chance = self.direct_messages.chance.unwrap_or(1.0);
+        if !(0.0..=1.0).contains(&dm_chance) {
+            errors.push(JokeError {
+                joke: "direct_messages".to_string(),
+                field: "chance",
+                error: "must be between 0 and 1".to_string(),
+            });
+        }
+
+        if !errors.is_empty() {
+            return Err(ConfigError::Invalid(errors));
+        }
+        Ok(Config {
+            invite: self.invite,
+            jokes,
+            direct_messages: DirectMessages {
+                enabled: self.direct_messages.enabled,
+                chance: dm_chance,
+                jokes: dm_jokes,
+            },
+            max_message_length: self
+                .max_message_length
+                .unwrap_or(DEFAULT_MAX_MESSAGE_LENGTH),
+        })
+    }
+}
+
+/// Compiles a list of jokes, whose names have to be unique.
+fn compile_jokes(raw_jokes: Vec<RawJoke>, errors: &mut Vec<JokeError>) -> Vec<Joke> {
+    let mut names = HashSet::new();
+    let mut jokes = Vec::with_capacity(raw_jokes.len());
+    for joke in raw_jokes {
+        if !names.insert(joke.name.clone()) {
+            errors.push(JokeError {
+                joke: joke.name.clone(),
+                field: "name",
+                error: "duplicate joke name".to_string(),
+            });
+        }
+        match joke.compile() {
+            Ok(joke) => jokes.push(joke),
+            Err(joke_errors) => errors.extend(joke_errors),
+        }
+    }
+    jokes
+}
+
+/// Parses and validates the contents of a config file.
+pub fn parse_config(contents: &str) -> Result<Config, ConfigError> {
+    let raw: RawConfig = serde_json::from_str(contents)?;
+    raw.validate()
+}
+
+pub fn read_config_from(path: impl AsRef<Path>) -> Result<Config, ConfigError> {
+    parse_config(&fs::read_to_string(path)?)
+}
+
+/// Reads and parses the config file, returning an error instead of panicking
+/// if the file is missing or any joke is invalid.
+pub fn read_config() -> Result<Config, ConfigError> {
     // load from current directory or the data directory
-    let contents = fs::read_to_string(file_path).unwrap_or_else(|_| {
-        fs::read_to_string("data/config.json")
-            .expect("Failed to read config file. Please create a config.json file.")
-    });
-    let p: Config = serde_json::from_str(&contents).expect("Failed to parse json");
-    p
+    let contents = match fs::read_to_string("config.json") {
+        Ok(contents) => contents,
+        Err(_) => fs::read_to_string("data/config.json")?,
+    };
+    parse_config(&contents)
+}
+
+pub fn load_config() -> Config {
+    read_config().unwrap_or_else(|e| panic!("Failed to load config file: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(jokes: serde_json::Value) -> Result<Config, ConfigError> {
+        parse_config(&json!({ "invite": "https://example.com", "jokes": jokes }).to_string())
+    }
+
+    fn errors(jokes: serde_json::Value) -> Vec<JokeError> {
+        match config(jokes) {
+            Err(ConfigError::Invalid(errors)) => errors,
+            Err(e) => panic!("expected invalid jokes, got {}", e),
+            Ok(_) => panic!("expected invalid jokes, the config is valid"),
+        }
+    }
+
+    fn joke_with_locale(locale: Option<&str>) -> Joke {
+        RawJoke {
+            name: "joke".to_string(),
+            regex: Some("a".to_string()),
+            message: vec!["joke".to_string().into()],
+            locale: locale.map(String::from),
+            ..Default::default()
+        }
+        .compile()
+        .unwrap()
+    }
+
+    #[test]
+    fn reports_an_invalid_regex() {
+        let errors =
+            errors(json!([{ "name": "broken", "regex": "(unclosed", "message": ["joke"] }]));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].joke, "broken");
+        assert_eq!(errors[0].field, "trigger");
+    }
+
+    #[test]
+    fn reports_a_duplicate_name() {
+        let errors = errors(json!([
+            { "name": "twice", "regex": "a", "message": ["joke"] },
+            { "name": "twice", "regex": "b", "message": ["joke"] },
+        ]));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].joke, "twice");
+        assert_eq!(errors[0].field, "name");
+        assert_eq!(errors[0].error, "duplicate joke name");
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let errors = errors(json!([
+            { "name": "fine", "regex": "a", "message": ["joke"] },
+            { "name": "regex", "regex": "(unclosed", "message": ["joke"] },
+            { "name": "fine", "regex": "b", "message": ["joke"] },
+            { "name": "empty", "regex": "c", "message": [] },
+            { "name": "captures", "regex": "d", "message": ["--[2]--"], "locale": "not a locale" },
+        ]));
+        let reported: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|error| (error.joke.as_str(), error.field))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                ("regex", "trigger"),
+                ("fine", "name"),
+                ("empty", "message"),
+                ("captures", "locale"),
+                ("captures", "message"),
+            ]
+        );
+        // the error message lists all of them
+        let message = ConfigError::Invalid(errors).to_string();
+        assert!(message.starts_with("config has 5 problem(s):"));
+        assert_eq!(message.lines().count(), 6);
+    }
+
+    #[test]
+    fn accepts_a_valid_config() {
+        let config = config(json!([
+            { "name": "regex", "regex": "(a)", "message": ["--[1]--"] },
+            { "name": "mention", "trigger": { "type": "mention" }, "message": ["joke"] },
+        ]))
+        .unwrap();
+        assert_eq!(config.jokes.len(), 2);
+    }
+
+    #[test]
+    fn validates_locales() {
+        for locale in ["de", "en-US", "pt-BR", "es-419", "fil"] {
+            assert!(is_valid_locale(locale), "{}", locale);
+        }
+        for locale in ["", "*", "e", "english", "en-", "en-US-x", "en_US", "d3"] {
+            assert!(!is_valid_locale(locale), "{}", locale);
+        }
+    }
+
+    #[test]
+    fn matches_locales_with_region_fallback() {
+        let english = joke_with_locale(Some("en"));
+        assert!(english.is_for_locale("en"));
+        assert!(english.is_for_locale("en-US"));
+        assert!(english.is_for_locale("EN-gb"));
+        assert!(!english.is_for_locale("de"));
+        // a language that starts the same isn't the same language
+        assert!(!joke_with_locale(Some("fi")).is_for_locale("fil"));
+
+        let american = joke_with_locale(Some("en-US"));
+        assert!(american.is_for_locale("en-US"));
+        assert!(!american.is_for_locale("en"));
+        assert!(!american.is_for_locale("en-GB"));
+    }
+
+    #[test]
+    fn any_locale_fits_every_guild() {
+        let any = joke_with_locale(Some(ANY_LOCALE));
+        assert_eq!(any.locale, None);
+        assert!(any.is_for_locale("de"));
+        assert!(any.is_for_locale(DEFAULT_LOCALE));
+        assert!(joke_with_locale(None).is_for_locale("ja"));
+    }
+
+    #[test]
+    fn config_jokes_get_the_default_locale() {
+        let config = config(json!([
+            { "name": "default", "regex": "a", "message": ["joke"] },
+            { "name": "german", "regex": "a", "message": ["Witz"], "locale": "de" },
+            { "name": "any", "regex": "a", "message": ["😂"], "locale": "*" },
+        ]))
+        .unwrap();
+        let locales: Vec<Option<&str>> = config
+            .jokes
+            .iter()
+            .map(|joke| joke.locale.as_deref())
+            .collect();
+        assert_eq!(locales, vec![Some(DEFAULT_LOCALE), Some("de"), None]);
+        assert!(!config.jokes[0].is_for_locale("de"));
+        assert!(config.jokes[2].is_for_locale("de"));
+    }
 }
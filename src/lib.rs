@@ -1,20 +1,26 @@
 pub mod commands;
 pub mod config;
+pub mod matcher;
 pub mod messages;
+pub mod reactions;
+pub mod template;
 pub mod voice_state_update;
 
 pub use commands::*;
 pub use config::*;
 pub use messages::*;
+pub use reactions::*;
 pub use voice_state_update::*;
 
+use matcher::JokeMatcher;
 use serde::{Deserialize, Serialize};
-use serenity::all::{Message, UserId};
+use serenity::all::{GuildId, Message, UserId};
 use serenity::model::id::ChannelId;
 use serenity::model::voice::VoiceState;
 use serenity::prelude::*;
 use sqlx::SqlitePool;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserIDGuildID {
@@ -23,9 +29,25 @@ pub struct UserIDGuildID {
     pub disconnect_message: Option<bool>,
 }
 
+/// A voice channel or category a user on the ping list is pinged for.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VcPingChannel {
+    pub user_id: i64,
+    pub guild_id: i64,
+    pub channel_id: i64,
+}
+
 pub struct State {
     pub pool: SqlitePool,
     pub occupied_channels: HashSet<ChannelId>,
+    /// compiled jokes from the `Jokes` table, loaded on first use per guild
+    pub guild_jokes: HashMap<GuildId, Vec<Joke>>,
+    /// matchers over the guild and global jokes, built on first use per guild
+    pub matchers: HashMap<GuildId, Arc<JokeMatcher>>,
+    /// matcher over the jokes about direct messages, built on first use
+    pub dm_matcher: Option<Arc<JokeMatcher>>,
+    pub cooldowns: Cooldowns,
+    pub conversations: Conversations,
 }
 
 impl TypeMapKey for State {
@@ -54,6 +76,14 @@ pub async fn get_numer_of_users_in_channel(ctx: &Context, state: &VoiceState) ->
     }
 }
 
+/// Whether the user is the owner of the bot, set with `OWNER_ID`.
+pub fn is_owner(user_id: UserId) -> bool {
+    std::env::var("OWNER_ID")
+        .ok()
+        .and_then(|owner_id| owner_id.parse::<UserId>().ok())
+        == Some(user_id)
+}
+
 pub fn should_respond(msg: &Message) -> bool {
     const HOOTSIFER_BOT_ID: UserId = UserId::new(896781020056145931);
 
@@ -4,7 +4,7 @@ use serenity::async_trait;
 use sqlx::migrate::MigrateDatabase;
 use sqlx::sqlite::SqlitePoolOptions;
 use sqlx::Sqlite;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use tracing::*;
 use tracing_subscriber::prelude::*;
 
@@ -17,30 +17,85 @@ impl EventHandler for Bot {
             return;
         }
 
-        if msg.author.id
-            == std::env::var("OWNER_ID")
-                .unwrap()
-                .parse::<serenity::model::id::UserId>()
-                .unwrap()
-        {
+        if is_owner(msg.author.id) {
             handle_owner_message(&ctx, &msg).await;
         }
 
         handle_jokes_message(&ctx, &msg).await;
     }
 
+    async fn message_update(
+        &self,
+        ctx: Context,
+        _old_if_available: Option<Message>,
+        new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        handle_jokes_message_update(&ctx, new, &event).await;
+    }
+
+    async fn message_delete(
+        &self,
+        ctx: Context,
+        channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        guild_id: Option<GuildId>,
+    ) {
+        handle_jokes_message_delete(&ctx, guild_id, channel_id, deleted_message_id).await;
+    }
+
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         if let Interaction::Command(command) = interaction {
             match command.data.name.as_str() {
                 "vcping" => {
                     handle_vcping_command(&ctx, &command).await;
                 }
+                "vcping-config" => {
+                    handle_vcping_config_command(&ctx, &command).await;
+                }
                 "joke-config" => {
                     handle_joke_config_command(&ctx, &command).await;
                 }
+                "joke" => {
+                    handle_joke_command(&ctx, &command).await;
+                }
+                "jokes" => {
+                    handle_jokes_command(&ctx, &command).await;
+                }
 
                 command => unreachable!("Unknown command: {}", command),
             };
+        } else if let Interaction::Autocomplete(command) = interaction {
+            match command.data.name.as_str() {
+                "joke-config" => {
+                    handle_joke_config_autocomplete(&ctx, &command).await;
+                }
+
+                command => unreachable!("Unknown autocomplete command: {}", command),
+            };
+        }
+    }
+
+    async fn reaction_add(&self, ctx: Context, add_reaction: Reaction) {
+        handle_jokes_reaction(&ctx, &add_reaction).await;
+        handle_reaction_add(&ctx, add_reaction).await;
+    }
+
+    async fn reaction_remove(&self, ctx: Context, removed_reaction: Reaction) {
+        handle_reaction_remove(&ctx, removed_reaction).await;
+    }
+
+    async fn guild_update(
+        &self,
+        ctx: Context,
+        old_data_if_available: Option<Guild>,
+        new_data: PartialGuild,
+    ) {
+        // the jokes depend on the server language, unless the guild chose one
+        let locale_changed = old_data_if_available
+            .is_none_or(|old| old.preferred_locale != new_data.preferred_locale);
+        if locale_changed {
+            invalidate_guild_jokes(&ctx, new_data.id).await;
         }
     }
 
@@ -59,26 +114,516 @@ impl EventHandler for Bot {
                     .description(
                         "Get added to the list of UserIDGuildID to ping when someone starts a VC",
                     )
+                    .description_localized("de", "Werde gepingt, wenn jemand einen Sprachkanal startet")
+                    .dm_permission(false)
                     .add_option(
                         CreateCommandOption::new(
                             CommandOptionType::Boolean,
                             "disconnect-message",
                             "Also send a message when someone disconnects from VC",
                         )
+                        .description_localized("de", "Auch eine Nachricht senden, wenn jemand den Sprachkanal verlässt")
+                        .required(false),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Channel,
+                            "channel",
+                            "Only get pinged for this voice channel or category, use again to remove it",
+                        )
+                        .description_localized(
+                            "de",
+                            "Nur für diesen Sprachkanal oder diese Kategorie pingen, erneut zum Entfernen",
+                        )
+                        .required(false)
+                        .channel_types(vec![
+                            ChannelType::Voice,
+                            ChannelType::Stage,
+                            ChannelType::Category,
+                        ]),
+                    ),
+                CreateCommand::new("vcping-config")
+                    .description("Post in a channel when someone starts a VC")
+                    .name_localized("de", "vcping-einstellungen")
+                    .description_localized(
+                        "de",
+                        "In einem Kanal posten, wenn jemand einen Sprachkanal startet",
+                    )
+                    .default_member_permissions(Permissions::ADMINISTRATOR)
+                    .dm_permission(false)
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Channel,
+                            "channel",
+                            "The channel to post in",
+                        )
+                        .description_localized("de", "Der Kanal, in dem gepostet wird")
+                        .required(false)
+                        .channel_types(vec![ChannelType::Text, ChannelType::News]),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::String,
+                            "webhook",
+                            "A webhook url to post with, instead of a channel",
+                        )
+                        .description_localized("de", "Eine Webhook-URL, statt eines Kanals")
+                        .required(false),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Role,
+                            "role",
+                            "A role to mention in the posts",
+                        )
+                        .description_localized("de", "Eine Rolle, die in den Posts erwähnt wird")
+                        .required(false),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Boolean,
+                            "clear-role",
+                            "Stop mentioning a role in the posts",
+                        )
+                        .description_localized("de", "In den Posts keine Rolle mehr erwähnen")
+                        .required(false),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Boolean,
+                            "dms",
+                            "Whether users on the ping list still get a direct message",
+                        )
+                        .description_localized(
+                            "de",
+                            "Ob Personen auf der Pingliste weiterhin eine Direktnachricht bekommen",
+                        )
+                        .required(false),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Boolean,
+                            "disconnect-message",
+                            "Also post when a VC ends",
+                        )
+                        .description_localized("de", "Auch posten, wenn ein Sprachkanal endet")
+                        .required(false),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Boolean,
+                            "reset",
+                            "Stop posting and only send direct messages again",
+                        )
+                        .description_localized(
+                            "de",
+                            "Nicht mehr posten und wieder nur Direktnachrichten senden",
+                        )
                         .required(false),
                     ),
                 CreateCommand::new("joke-config")
                     .description("Configure how the bot should make jokes")
+                    .name_localized("de", "witz-einstellungen")
+                    .description_localized("de", "Lege fest, wie der Bot Witze macht")
                     .default_member_permissions(Permissions::ADMINISTRATOR)
+                    .dm_permission(false)
                     .add_option(
                         CreateCommandOption::new(
                             CommandOptionType::Integer,
                             "chance",
                             "The chance that the bot will make a joke",
                         )
+                        .description_localized("de", "Die Wahrscheinlichkeit, dass der Bot einen Witz macht")
+                        .required(false)
+                        .min_int_value(0)
+                        .max_int_value(100),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::String,
+                            "joke",
+                            "A single joke to configure",
+                        )
+                        .description_localized("de", "Ein einzelner Witz, der eingestellt werden soll")
+                        .required(false)
+                        .set_autocomplete(true),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Boolean,
+                            "enabled",
+                            "Whether the joke or channel is enabled",
+                        )
+                        .description_localized("de", "Ob der Witz oder Kanal aktiviert ist")
+                        .required(false),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "joke-chance",
+                            "The chance that the bot will make this joke, instead of the server chance",
+                        )
+                        .description_localized("de", "Die Wahrscheinlichkeit für diesen Witz, statt der des Servers")
+                        .required(false)
+                        .min_int_value(0)
+                        .max_int_value(100),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Channel,
+                            "channel",
+                            "A channel, thread or category to configure, chance and enabled then apply to it",
+                        )
+                        .description_localized("de", "Ein Kanal, Thread oder eine Kategorie, für die chance und enabled gelten")
+                        .required(false)
+                        .channel_types(vec![
+                            ChannelType::Text,
+                            ChannelType::News,
+                            ChannelType::Voice,
+                            ChannelType::Category,
+                            ChannelType::Forum,
+                            ChannelType::PublicThread,
+                            ChannelType::PrivateThread,
+                            ChannelType::NewsThread,
+                        ]),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Boolean,
+                            "reset",
+                            "Reset the joke or channel to the server settings",
+                        )
+                        .description_localized("de", "Den Witz oder Kanal auf die Servereinstellungen zurücksetzen")
+                        .required(false),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "joke-cooldown",
+                            "Seconds before the same joke can be made in a channel again",
+                        )
+                        .description_localized("de", "Sekunden, bis derselbe Witz in einem Kanal wieder gemacht werden kann")
+                        .required(false)
+                        .min_int_value(0),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "user-cooldown",
+                            "Seconds before the bot makes a joke about the same user again",
+                        )
+                        .description_localized("de", "Sekunden, bis der Bot wieder einen Witz über dieselbe Person macht")
+                        .required(false)
+                        .min_int_value(0),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "server-cooldown",
+                            "Seconds before the bot makes any joke in this server again",
+                        )
+                        .description_localized("de", "Sekunden, bis der Bot auf diesem Server wieder einen Witz macht")
+                        .required(false)
+                        .min_int_value(0),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::String,
+                            "match-strategy",
+                            "Which jokes to make if several jokes match a message",
+                        )
+                        .description_localized("de", "Welche Witze gemacht werden, wenn mehrere zu einer Nachricht passen")
+                        .required(false)
+                        .add_string_choice_localized("First by priority", "first", [("de", "Erster nach Priorität")])
+                        .add_string_choice_localized("Random", "random", [("de", "Zufällig")])
+                        .add_string_choice_localized("All, up to max-matches", "all", [("de", "Alle, bis zu max-matches")]),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "max-matches",
+                            "The maximum number of jokes for one message with the all strategy",
+                        )
+                        .description_localized("de", "Die maximale Anzahl an Witzen pro Nachricht mit der Strategie all")
+                        .required(false)
+                        .min_int_value(1),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "downvotes-to-delete",
+                            "Delete a joke after this many 👎 or 🗑️ reactions, 0 to never delete",
+                        )
+                        .description_localized("de", "Einen Witz nach so vielen 👎 oder 🗑️ Reaktionen löschen, 0 für nie")
+                        .required(false)
+                        .min_int_value(0),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "auto-lower-threshold",
+                            "Lower the chance of jokes downvoted more often than this percentage, 0 to disable",
+                        )
+                        .description_localized("de", "Die Chance von Witzen senken, die öfter als in diesem Prozentsatz abgelehnt werden, 0 für nie")
                         .required(false)
                         .min_int_value(0)
                         .max_int_value(100),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Boolean,
+                            "evaluate-edits",
+                            "Check edited messages for jokes again and delete jokes that no longer match",
+                        )
+                        .description_localized("de", "Bearbeitete Nachrichten erneut prüfen und unpassende Witze löschen")
+                        .required(false),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Number,
+                            "delay-min",
+                            "Shortest time in seconds to show the typing indicator before a joke",
+                        )
+                        .description_localized("de", "Kürzeste Zeit in Sekunden, die vor einem Witz getippt wird")
+                        .required(false)
+                        .min_number_value(0.0)
+                        .max_number_value(MAX_DELAY),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Number,
+                            "delay-max",
+                            "Longest delay before a joke in seconds, set both to 0 to disable",
+                        )
+                        .description_localized("de", "Längste Verzögerung vor einem Witz in Sekunden, beide auf 0 zum Deaktivieren")
+                        .required(false)
+                        .min_number_value(0.0)
+                        .max_number_value(MAX_DELAY),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::String,
+                            "locale",
+                            "Only make jokes in this language, like de or en-US, auto for the server language",
+                        )
+                        .description_localized("de", "Nur Witze in dieser Sprache machen, etwa de oder en-US, auto für die Serversprache")
+                        .required(false),
+                    ),
+                CreateCommand::new("joke")
+                    .description("Manage the jokes of this server")
+                    .name_localized("de", "witz")
+                    .description_localized("de", "Verwalte die Witze dieses Servers")
+                    .default_member_permissions(Permissions::ADMINISTRATOR)
+                    .dm_permission(false)
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "add",
+                            "Add a joke to this server",
+                        )
+                        .description_localized("de", "Einen Witz zu diesem Server hinzufügen")
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "name",
+                                "The name of the joke",
+                            )
+                            .description_localized("de", "Der Name des Witzes")
+                            .required(true),
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "regex",
+                                "The regex that triggers the joke",
+                            )
+                            .description_localized("de", "Die Regex, die den Witz auslöst")
+                            .required(true),
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "message",
+                                "The messages to respond with, separated by ;;",
+                            )
+                            .description_localized("de", "Die Antworten, getrennt durch ;;")
+                            .required(true),
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::Boolean,
+                                "reply",
+                                "Reply to the message instead of just sending the joke",
+                            )
+                            .description_localized("de", "Auf die Nachricht antworten, statt den Witz nur zu senden")
+                            .required(false),
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::Integer,
+                                "priority",
+                                "Jokes with a higher priority are checked first",
+                            )
+                            .description_localized("de", "Witze mit höherer Priorität werden zuerst geprüft")
+                            .required(false),
+                        ),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "remove",
+                            "Remove a joke from this server",
+                        )
+                        .description_localized("de", "Einen Witz von diesem Server entfernen")
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "name",
+                                "The name of the joke",
+                            )
+                            .description_localized("de", "Der Name des Witzes")
+                            .required(true),
+                        ),
+                    )
+                    .add_option(CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "list",
+                        "List the jokes of this server",
+                    )
+                    .description_localized("de", "Die Witze dieses Servers auflisten"))
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "stats",
+                            "Show which jokes were made and who triggered them",
+                        )
+                        .description_localized("de", "Zeigen, welche Witze gemacht wurden und wer sie ausgelöst hat")
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::Boolean,
+                                "global",
+                                "Show statistics for all servers, only for the bot owner",
+                            )
+                            .description_localized("de", "Statistiken aller Server zeigen, nur für den Besitzer des Bots")
+                            .required(false),
+                        ),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "test",
+                            "Show which joke a message would trigger, without sending it",
+                        )
+                        .description_localized("de", "Zeigen, welchen Witz eine Nachricht auslösen würde, ohne ihn zu senden")
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "text",
+                                "The message to test",
+                            )
+                            .description_localized("de", "Die zu testende Nachricht")
+                            .required(true),
+                        ),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "edit",
+                            "Edit a joke of this server",
+                        )
+                        .description_localized("de", "Einen Witz dieses Servers bearbeiten")
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "name",
+                                "The name of the joke",
+                            )
+                            .description_localized("de", "Der Name des Witzes")
+                            .required(true),
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "regex",
+                                "The regex that triggers the joke",
+                            )
+                            .description_localized("de", "Die Regex, die den Witz auslöst")
+                            .required(false),
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::String,
+                                "message",
+                                "The messages to respond with, separated by ;;",
+                            )
+                            .description_localized("de", "Die Antworten, getrennt durch ;;")
+                            .required(false),
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::Boolean,
+                                "reply",
+                                "Reply to the message instead of just sending the joke",
+                            )
+                            .description_localized("de", "Auf die Nachricht antworten, statt den Witz nur zu senden")
+                            .required(false),
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::Boolean,
+                                "enabled",
+                                "Whether the joke is enabled",
+                            )
+                            .description_localized("de", "Ob der Witz aktiviert ist")
+                            .required(false),
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::Integer,
+                                "priority",
+                                "Jokes with a higher priority are checked first",
+                            )
+                            .description_localized("de", "Witze mit höherer Priorität werden zuerst geprüft")
+                            .required(false),
+                        ),
+                    ),
+                CreateCommand::new("jokes")
+                    .description("Choose whether the bot makes jokes about your messages")
+                    .name_localized("de", "witze")
+                    .description_localized("de", "Wähle, ob der Bot Witze über deine Nachrichten macht")
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "opt-out",
+                            "Stop the bot from making jokes about your messages",
+                        )
+                        .description_localized("de", "Der Bot soll keine Witze mehr über deine Nachrichten machen")
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::Boolean,
+                                "global",
+                                "Opt out in every server, not just this one",
+                            )
+                            .description_localized("de", "Auf allen Servern abmelden, nicht nur auf diesem")
+                            .required(false),
+                        ),
+                    )
+                    .add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::SubCommand,
+                            "opt-in",
+                            "Allow the bot to make jokes about your messages again",
+                        )
+                        .description_localized("de", "Dem Bot wieder Witze über deine Nachrichten erlauben")
+                        .add_sub_option(
+                            CreateCommandOption::new(
+                                CommandOptionType::Boolean,
+                                "global",
+                                "Undo an opt-out from every server",
+                            )
+                            .description_localized("de", "Eine Abmeldung von allen Servern zurücknehmen")
+                            .required(false),
+                        ),
                     ),
             ],
         )
@@ -107,6 +652,105 @@ async fn main() {
         .with(tracing_subscriber::fmt::Layer::default())
         .init();
 
+    // `--check-config [path]` only validates the config and exits
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--check-config") {
+        let result = match args.get(2) {
+            Some(path) => config::read_config_from(path),
+            None => config::read_config(),
+        };
+        match result {
+            Ok(config) => {
+                println!("Config is valid ({} jokes)", config.jokes.len());
+                return;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // `--test-joke <message> [guild id] [locale] [--config <path>]` shows which global joke
+    // the message triggers and exits
+    if args.get(1).map(String::as_str) == Some("--test-joke") {
+        let usage = || -> ! {
+            eprintln!("Usage: --test-joke <message> [guild id] [locale] [--config <path>]");
+            std::process::exit(1);
+        };
+        let Some(content) = args.get(2) else {
+            usage();
+        };
+        let mut rest: Vec<&String> = args.iter().skip(3).collect();
+        let config_path = match rest.iter().position(|arg| *arg == "--config") {
+            Some(i) if i + 1 < rest.len() => {
+                let path = rest.remove(i + 1);
+                rest.remove(i);
+                Some(path)
+            }
+            Some(_) => usage(),
+            None => None,
+        };
+        let guild_id = rest.first().map(|guild_id| {
+            guild_id.parse::<GuildId>().unwrap_or_else(|_| {
+                eprintln!("Invalid guild id: {}", guild_id);
+                std::process::exit(1);
+            })
+        });
+        let locale = rest.get(1).map_or(DEFAULT_LOCALE, |locale| locale.as_str());
+        let config = match config_path {
+            Some(path) => config::read_config_from(path),
+            None => config::read_config(),
+        }
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        if content.chars().count() > config.max_message_length {
+            println!(
+                "Messages longer than {} characters are not checked for jokes",
+                config.max_message_length
+            );
+            return;
+        }
+        let mut jokes: Vec<Joke> = config
+            .jokes
+            .into_iter()
+            .filter(|joke| {
+                joke.servers
+                    .as_ref()
+                    .is_none_or(|servers| guild_id.is_some_and(|id| servers.contains(&id)))
+                    && joke.is_for_locale(locale)
+            })
+            .collect();
+        jokes.sort_by_key(|joke| std::cmp::Reverse(joke.priority));
+        let matcher = matcher::JokeMatcher::new(jokes);
+        let allowed = vec![true; matcher.jokes().len()];
+        // there is no message, so the placeholders show their names
+        let context = template::TemplateContext {
+            nickname: "nickname".to_string(),
+            username: "username".to_string(),
+            guild: "guild".to_string(),
+            channel: "channel".to_string(),
+            ..Default::default()
+        };
+        println!(
+            "{}",
+            dry_run(
+                &matcher,
+                &matcher::TriggerEvent {
+                    content: content.to_string(),
+                    ..Default::default()
+                },
+                &allowed,
+                MatchStrategy::First,
+                &context,
+                &mut rand::thread_rng(),
+            )
+        );
+        return;
+    }
+
     info!("Starting bot version {}", env!("CARGO_PKG_VERSION"));
 
     let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
@@ -138,11 +782,18 @@ async fn main() {
     let intents = GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::GUILDS
         | GatewayIntents::MESSAGE_CONTENT
-        | GatewayIntents::GUILD_VOICE_STATES;
+        | GatewayIntents::GUILD_MESSAGE_REACTIONS
+        | GatewayIntents::GUILD_VOICE_STATES
+        | GatewayIntents::DIRECT_MESSAGES;
 
     let state = State {
         pool,
         occupied_channels: HashSet::new(),
+        guild_jokes: HashMap::new(),
+        matchers: HashMap::new(),
+        dm_matcher: None,
+        cooldowns: Cooldowns::default(),
+        conversations: Conversations::default(),
     };
     let config = config::load_config();
 
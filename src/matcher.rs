@@ -0,0 +1,366 @@
+use regex::{RegexSet, RegexSetBuilder};
+use serenity::all::{Message, ReactionType, RoleId, UserId};
+
+use crate::config::{Joke, Trigger};
+use crate::template::CaptureValues;
+use tracing::warn;
+
+/// Cache size of the lazy DFA behind the `RegexSet`. The default of 2 MiB is too small
+/// for the long, case insensitive word lists some jokes use, and a full cache makes
+/// the set fall back to a much slower engine.
+const DFA_SIZE_LIMIT: usize = 8 << 20;
+
+/// What happened, for the triggers of the jokes to match against.
+#[derive(Clone, Debug, Default)]
+pub struct TriggerEvent {
+    pub content: String,
+    pub mentions_bot: bool,
+    /// content types of the attachments, empty if discord didn't send one
+    pub attachment_types: Vec<String>,
+    pub author_roles: Vec<RoleId>,
+    /// the reaction that was added, only reaction triggers match if set
+    pub reaction: Option<ReactionType>,
+}
+
+impl TriggerEvent {
+    /// The event of a message being sent by its author.
+    pub fn from_message(msg: &Message, bot_id: UserId) -> Self {
+        TriggerEvent {
+            content: msg.content.clone(),
+            mentions_bot: msg.mentions.iter().any(|user| user.id == bot_id),
+            attachment_types: msg
+                .attachments
+                .iter()
+                .map(|attachment| {
+                    attachment
+                        .content_type
+                        .clone()
+                        .unwrap_or_default()
+                        .to_lowercase()
+                })
+                .collect(),
+            author_roles: msg
+                .member
+                .as_ref()
+                .map(|member| member.roles.clone())
+                .unwrap_or_default(),
+            reaction: None,
+        }
+    }
+
+    /// The event of the reaction being added to a message.
+    pub fn from_reaction(reaction: ReactionType) -> Self {
+        TriggerEvent {
+            reaction: Some(reaction),
+            ..Default::default()
+        }
+    }
+}
+
+/// Checks a single trigger, returning the captures if it matches.
+/// Triggers without a regex have no captures.
+pub fn match_trigger(
+    trigger: &Trigger,
+    event: &TriggerEvent,
+) -> Result<Option<CaptureValues>, fancy_regex::Error> {
+    let matches = match (trigger, &event.reaction) {
+        (Trigger::Reaction(reaction), Some(added)) => reaction == added,
+        // a reaction doesn't say anything new about the message
+        (_, Some(_)) | (Trigger::Reaction(_), None) => false,
+        (Trigger::Regex(regex) | Trigger::Keywords { regex, .. }, None) => {
+            return Ok(regex
+                .captures(&event.content)?
+                .map(|captures| CaptureValues::new(regex, &captures)));
+        }
+        (Trigger::Mention, None) => event.mentions_bot,
+        (Trigger::Attachment(content_type), None) => event
+            .attachment_types
+            .iter()
+            .any(|attachment_type| attachment_type.starts_with(content_type.as_str())),
+        (Trigger::Role(role_id), None) => event.author_roles.contains(role_id),
+    };
+    Ok(matches.then(CaptureValues::default))
+}
+
+/// Matches events against a fixed list of jokes.
+///
+/// Regexes without fancy features (lookarounds, backreferences, ...) are also compiled
+/// into a single `RegexSet`, which tells in one pass which of them can match at all.
+/// Only those, the jokes with fancy regexes and the jokes with other triggers are then
+/// checked on their own.
+pub struct JokeMatcher {
+    jokes: Vec<Joke>,
+    set: RegexSet,
+    /// index into `jokes` for every pattern in `set`
+    set_jokes: Vec<usize>,
+    /// whether the joke can't be ruled out by the set
+    always_check: Vec<bool>,
+}
+
+impl JokeMatcher {
+    /// Builds the matcher, keeping the order of `jokes`.
+    pub fn new(jokes: Vec<Joke>) -> Self {
+        let mut patterns = Vec::new();
+        let mut set_jokes = Vec::new();
+        let mut always_check = Vec::with_capacity(jokes.len());
+        for (i, joke) in jokes.iter().enumerate() {
+            // the regex crate rejects everything that needs fancy_regex
+            let plain = joke
+                .trigger
+                .regex()
+                .filter(|regex| regex::Regex::new(regex.as_str()).is_ok());
+            if let Some(regex) = plain {
+                patterns.push(regex.as_str().to_string());
+                set_jokes.push(i);
+            }
+            always_check.push(plain.is_none());
+        }
+        let set = RegexSetBuilder::new(&patterns)
+            .dfa_size_limit(DFA_SIZE_LIMIT)
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Could not build regex set, checking every joke: {}", e);
+                always_check.iter_mut().for_each(|always| *always = true);
+                set_jokes.clear();
+                RegexSet::empty()
+            });
+
+        JokeMatcher {
+            jokes,
+            set,
+            set_jokes,
+            always_check,
+        }
+    }
+
+    pub fn jokes(&self) -> &[Joke] {
+        &self.jokes
+    }
+
+    /// Indices of the jokes that could match the content, in order.
+    pub fn candidates(&self, content: &str) -> Vec<usize> {
+        let mut candidates = vec![false; self.jokes.len()];
+        for pattern in self.set.matches(content).iter() {
+            candidates[self.set_jokes[pattern]] = true;
+        }
+        (0..self.jokes.len())
+            .filter(|i| candidates[*i] || self.always_check[*i])
+            .collect()
+    }
+
+    /// Checks the jokes that are `allowed` against the event in order,
+    /// stopping at the first match unless `all` is set.
+    /// Jokes whose regex fails, e.g. by hitting the backtrack limit, are skipped.
+    pub fn find(
+        &self,
+        event: &TriggerEvent,
+        allowed: &[bool],
+        all: bool,
+    ) -> Vec<(Joke, CaptureValues)> {
+        let mut matched = Vec::new();
+        for i in self.candidates(&event.content) {
+            if !allowed[i] {
+                continue;
+            }
+            let joke = &self.jokes[i];
+            match match_trigger(&joke.trigger, event) {
+                Ok(Some(captures)) => {
+                    matched.push((joke.clone(), captures));
+                    if !all {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Skipping joke {}, its regex failed: {}", joke.name, e),
+            }
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RawJoke;
+    use serde_json::json;
+
+    fn joke(name: &str, priority: i64, trigger: serde_json::Value) -> Joke {
+        let raw: RawJoke = serde_json::from_value(json!({
+            "name": name,
+            "trigger": trigger,
+            "message": ["joke"],
+            "priority": priority,
+        }))
+        .unwrap();
+        raw.compile().unwrap()
+    }
+
+    /// Jokes with every kind of trigger, sorted by priority like the matchers of guilds.
+    fn jokes() -> Vec<Joke> {
+        let mut jokes = vec![
+            joke("plain", 0, json!({ "type": "regex", "regex": "(?i)hello" })),
+            joke(
+                "lookbehind",
+                5,
+                json!({ "type": "regex", "regex": "(?<=foo)bar" }),
+            ),
+            joke(
+                "backreference",
+                1,
+                json!({ "type": "regex", "regex": r"(\w)\1" }),
+            ),
+            joke(
+                "keywords",
+                3,
+                json!({ "type": "keywords", "keywords": ["car", "bike"] }),
+            ),
+            joke("mention", 2, json!({ "type": "mention" })),
+            joke("role", 4, json!({ "type": "role", "role": "42" })),
+            joke("numbers", 0, json!({ "type": "regex", "regex": r"\d{3}" })),
+        ];
+        jokes.sort_by_key(|joke| std::cmp::Reverse(joke.priority));
+        jokes
+    }
+
+    /// The names of the jokes that match the event, checking every trigger on its own.
+    fn matching_one_by_one(jokes: &[Joke], event: &TriggerEvent) -> Vec<String> {
+        jokes
+            .iter()
+            .filter(|joke| match_trigger(&joke.trigger, event).unwrap().is_some())
+            .map(|joke| joke.name.clone())
+            .collect()
+    }
+
+    fn names(matched: &[(Joke, CaptureValues)]) -> Vec<String> {
+        matched.iter().map(|(joke, _)| joke.name.clone()).collect()
+    }
+
+    fn message(content: &str) -> TriggerEvent {
+        TriggerEvent {
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn find_matches_checking_every_joke() {
+        let matcher = JokeMatcher::new(jokes());
+        let allowed = vec![true; matcher.jokes().len()];
+        let events = vec![
+            message(""),
+            message("nothing to see"),
+            message("Hello there"),
+            message("foobar"),
+            message("barfoo"),
+            message("a car and a bike"),
+            message("scary carpet"),
+            message("call 555 now, hello"),
+            TriggerEvent {
+                mentions_bot: true,
+                ..message("hi bot")
+            },
+            TriggerEvent {
+                author_roles: vec![RoleId::new(42)],
+                ..message("foobar with my car")
+            },
+            TriggerEvent {
+                author_roles: vec![RoleId::new(7)],
+                ..message("hello")
+            },
+        ];
+        for event in &events {
+            let expected = matching_one_by_one(matcher.jokes(), event);
+            assert_eq!(
+                names(&matcher.find(event, &allowed, true)),
+                expected,
+                "content: {:?}",
+                event.content
+            );
+            assert_eq!(
+                names(&matcher.find(event, &allowed, false)),
+                expected.into_iter().take(1).collect::<Vec<_>>(),
+                "content: {:?}",
+                event.content
+            );
+        }
+    }
+
+    #[test]
+    fn find_keeps_the_priority_order() {
+        let matcher = JokeMatcher::new(jokes());
+        let allowed = vec![true; matcher.jokes().len()];
+        let event = TriggerEvent {
+            author_roles: vec![RoleId::new(42)],
+            mentions_bot: true,
+            ..message("foobar, hello car 555 aa")
+        };
+        assert_eq!(
+            names(&matcher.find(&event, &allowed, true)),
+            vec![
+                "lookbehind",
+                "role",
+                "keywords",
+                "mention",
+                "backreference",
+                "plain",
+                "numbers"
+            ]
+        );
+        assert_eq!(
+            names(&matcher.find(&event, &allowed, false)),
+            vec!["lookbehind"]
+        );
+    }
+
+    #[test]
+    fn find_skips_jokes_that_are_not_allowed() {
+        let matcher = JokeMatcher::new(jokes());
+        let allowed: Vec<bool> = matcher
+            .jokes()
+            .iter()
+            .map(|joke| joke.name != "lookbehind" && joke.name != "plain")
+            .collect();
+        let event = message("foobar, hello car");
+        assert_eq!(
+            names(&matcher.find(&event, &allowed, true)),
+            vec!["keywords", "backreference"]
+        );
+        assert_eq!(
+            names(&matcher.find(&event, &allowed, false)),
+            vec!["keywords"]
+        );
+    }
+
+    #[test]
+    fn candidates_only_rule_out_plain_regexes() {
+        let matcher = JokeMatcher::new(jokes());
+        let candidates: Vec<&str> = matcher
+            .candidates("nothing to see")
+            .into_iter()
+            .map(|i| matcher.jokes()[i].name.as_str())
+            .collect();
+        // fancy regexes and triggers without a regex are always checked
+        assert_eq!(
+            candidates,
+            vec!["lookbehind", "role", "mention", "backreference"]
+        );
+    }
+
+    #[test]
+    fn reactions_only_match_reaction_triggers() {
+        let mut jokes = jokes();
+        jokes.push(joke(
+            "car",
+            0,
+            json!({ "type": "reaction", "emoji": "car" }),
+        ));
+        let matcher = JokeMatcher::new(jokes);
+        let allowed = vec![true; matcher.jokes().len()];
+        let event = TriggerEvent {
+            content: "hello car".to_string(),
+            ..TriggerEvent::from_reaction(ReactionType::Unicode("🚗".to_string()))
+        };
+        assert_eq!(matching_one_by_one(matcher.jokes(), &event), vec!["car"]);
+        assert_eq!(names(&matcher.find(&event, &allowed, true)), vec!["car"]);
+    }
+}
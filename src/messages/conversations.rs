@@ -0,0 +1,320 @@
+use serenity::{
+    all::{ChannelId, UserId},
+    client::Context,
+    model::channel::Message,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::{
+    config::{Joke, JokeResponse},
+    get_channel_rule, get_dm_joke_matcher, get_joke_matcher, get_joke_overrides, make_joke,
+    template::CaptureValues,
+    State,
+};
+
+/// A joke waiting for the next message of a user in a channel.
+pub struct OngoingConversation {
+    pub joke_name: String,
+    /// index of the next step of the joke's conversation
+    pub step: usize,
+    /// named captures of the earlier messages
+    pub named: HashMap<String, String>,
+    expires: Instant,
+}
+
+/// The conversations that are going on, per channel and user.
+#[derive(Default)]
+pub struct Conversations {
+    active: HashMap<(ChannelId, UserId), OngoingConversation>,
+}
+
+impl Conversations {
+    /// Waits for the step of the joke to be answered by the user, replacing any other conversation.
+    pub fn start(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        joke_name: &str,
+        step: usize,
+        named: HashMap<String, String>,
+        timeout: Duration,
+    ) {
+        let now = Instant::now();
+        self.start_at(
+            channel_id,
+            user_id,
+            OngoingConversation {
+                joke_name: joke_name.to_string(),
+                step,
+                named,
+                expires: now + timeout,
+            },
+            now,
+        );
+    }
+
+    fn start_at(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        conversation: OngoingConversation,
+        now: Instant,
+    ) {
+        self.active
+            .retain(|_, conversation| conversation.expires > now);
+        self.active.insert((channel_id, user_id), conversation);
+    }
+
+    /// Ends the conversation with the user, returning it if it hasn't expired.
+    pub fn take(&mut self, channel_id: ChannelId, user_id: UserId) -> Option<OngoingConversation> {
+        self.take_at(channel_id, user_id, Instant::now())
+    }
+
+    fn take_at(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        now: Instant,
+    ) -> Option<OngoingConversation> {
+        self.active
+            .remove(&(channel_id, user_id))
+            .filter(|conversation| conversation.expires > now)
+    }
+}
+
+/// Remembers the joke that was just made, if it goes on with the author's next message.
+pub async fn start_conversation(
+    ctx: &Context,
+    msg: &Message,
+    joke: &Joke,
+    captures: &CaptureValues,
+) {
+    let Some(conversation) = &joke.conversation else {
+        return;
+    };
+    let mut data = ctx.data.write().await;
+    let state = data.get_mut::<State>().unwrap();
+    state.conversations.start(
+        msg.channel_id,
+        msg.author.id,
+        &joke.name,
+        0,
+        captures.named.clone(),
+        conversation.timeout,
+    );
+}
+
+/// Answers the message if it continues a conversation with its author.
+/// Returns whether it did, in which case the message isn't checked for other jokes.
+/// A message that doesn't fit the conversation ends it.
+pub async fn handle_conversation_message(ctx: &Context, msg: &Message) -> bool {
+    let Some(conversation) = ctx
+        .data
+        .write()
+        .await
+        .get_mut::<State>()
+        .unwrap()
+        .conversations
+        .take(msg.channel_id, msg.author.id)
+    else {
+        return false;
+    };
+
+    let matcher = match msg.guild_id {
+        Some(guild_id) => get_joke_matcher(ctx, guild_id).await,
+        None => get_dm_joke_matcher(ctx).await,
+    };
+    // the joke may have been removed since the conversation started
+    let Some((joke, joke_conversation)) = matcher
+        .jokes()
+        .iter()
+        .find(|joke| joke.name == conversation.joke_name)
+        .and_then(|joke| Some((joke, joke.conversation.as_ref()?)))
+    else {
+        return false;
+    };
+    let Some(step) = joke_conversation.steps.get(conversation.step) else {
+        return false;
+    };
+
+    // the joke or the channel may have been disabled since the conversation started
+    if let Some(guild_id) = msg.guild_id {
+        if get_channel_rule(ctx, msg.channel_id)
+            .await
+            .is_some_and(|rule| !rule.enabled)
+        {
+            return false;
+        }
+        if get_joke_overrides(ctx, guild_id)
+            .await
+            .get(&joke.name)
+            .is_some_and(|joke_override| !joke_override.enabled)
+        {
+            return false;
+        }
+    }
+
+    // backtracking regexes can take a while, so don't block the executor with them
+    let regex = step.regex.clone();
+    let content = msg.content.clone();
+    let captures = tokio::task::spawn_blocking(move || {
+        regex
+            .captures(&content)
+            .map(|captures| captures.map(|captures| CaptureValues::new(&regex, &captures)))
+    })
+    .await
+    .unwrap();
+    let captures = match captures {
+        Ok(Some(captures)) => captures,
+        Ok(None) => return false,
+        Err(e) => {
+            warn!(
+                "Ending conversation of joke {}, its regex failed: {}",
+                joke.name, e
+            );
+            return false;
+        }
+    };
+    debug!(
+        "Continuing conversation of joke {} at step {}",
+        joke.name, conversation.step
+    );
+
+    let mut named = conversation.named;
+    named.extend(captures.named.clone());
+    let next_step = conversation.step + 1;
+    if next_step < joke_conversation.steps.len() {
+        let mut data = ctx.data.write().await;
+        let state = data.get_mut::<State>().unwrap();
+        state.conversations.start(
+            msg.channel_id,
+            msg.author.id,
+            &joke.name,
+            next_step,
+            named.clone(),
+            joke_conversation.timeout,
+        );
+    }
+
+    // the steps only answer with text
+    let step_joke = Joke {
+        message: step.message.clone(),
+        response: JokeResponse {
+            text: true,
+            reactions: Vec::new(),
+            embed: None,
+            attachment: None,
+            sticker: None,
+        },
+        conversation: None,
+        ..joke.clone()
+    };
+    let captures = CaptureValues {
+        numbered: captures.numbered,
+        named,
+    };
+    make_joke(ctx, msg, &step_joke, captures, step_joke.delay).await;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNEL: ChannelId = ChannelId::new(1);
+    const USER: UserId = UserId::new(2);
+    const OTHER_USER: UserId = UserId::new(3);
+
+    fn conversation(step: usize, named: &[(&str, &str)], expires: Instant) -> OngoingConversation {
+        OngoingConversation {
+            joke_name: "knock-knock".to_string(),
+            step,
+            named: named
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            expires,
+        }
+    }
+
+    fn after(now: Instant, seconds: u64) -> Instant {
+        now + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn advances_to_the_next_step() {
+        let now = Instant::now();
+        let mut conversations = Conversations::default();
+        conversations.start_at(CHANNEL, USER, conversation(0, &[], after(now, 60)), now);
+
+        let first = conversations
+            .take_at(CHANNEL, USER, after(now, 10))
+            .unwrap();
+        assert_eq!(first.step, 0);
+        // answering a step ends it until the next one is started
+        assert!(conversations
+            .take_at(CHANNEL, USER, after(now, 10))
+            .is_none());
+
+        conversations.start_at(
+            CHANNEL,
+            USER,
+            conversation(first.step + 1, &[("who", "Lettuce")], after(now, 70)),
+            after(now, 10),
+        );
+        let second = conversations
+            .take_at(CHANNEL, USER, after(now, 20))
+            .unwrap();
+        assert_eq!(second.joke_name, "knock-knock");
+        assert_eq!(second.step, 1);
+        assert_eq!(second.named.get("who").map(String::as_str), Some("Lettuce"));
+    }
+
+    #[test]
+    fn expires_after_the_timeout() {
+        let now = Instant::now();
+        let mut conversations = Conversations::default();
+        conversations.start_at(CHANNEL, USER, conversation(0, &[], after(now, 60)), now);
+        assert!(conversations
+            .take_at(CHANNEL, USER, after(now, 60))
+            .is_none());
+
+        // starting another conversation forgets the expired ones
+        conversations.start_at(CHANNEL, USER, conversation(0, &[], after(now, 60)), now);
+        conversations.start_at(
+            CHANNEL,
+            OTHER_USER,
+            conversation(0, &[], after(now, 120)),
+            after(now, 61),
+        );
+        assert_eq!(conversations.active.len(), 1);
+    }
+
+    #[test]
+    fn tracks_users_in_the_same_channel_separately() {
+        let now = Instant::now();
+        let mut conversations = Conversations::default();
+        conversations.start_at(CHANNEL, USER, conversation(0, &[], after(now, 60)), now);
+        conversations.start_at(
+            CHANNEL,
+            OTHER_USER,
+            conversation(2, &[], after(now, 60)),
+            now,
+        );
+
+        assert_eq!(
+            conversations
+                .take_at(CHANNEL, OTHER_USER, now)
+                .unwrap()
+                .step,
+            2
+        );
+        // the other user answering doesn't end the conversation with the first one
+        assert_eq!(conversations.take_at(CHANNEL, USER, now).unwrap().step, 0);
+        assert!(conversations
+            .take_at(ChannelId::new(9), USER, now)
+            .is_none());
+    }
+}
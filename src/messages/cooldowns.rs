@@ -0,0 +1,194 @@
+use serenity::all::{ChannelId, GuildId, UserId};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::JokeConfig;
+
+/// Tracks until when jokes are on cooldown, at the scopes configured in `JokeConfig`.
+#[derive(Default)]
+pub struct Cooldowns {
+    /// per joke name and channel
+    joke: HashMap<(String, ChannelId), Instant>,
+    /// per user in a guild
+    user: HashMap<(GuildId, UserId), Instant>,
+    /// per guild, for all jokes
+    guild: HashMap<GuildId, Instant>,
+}
+
+fn seconds(cooldown: Option<i64>) -> Option<Duration> {
+    cooldown
+        .filter(|seconds| *seconds > 0)
+        .map(|seconds| Duration::from_secs(seconds as u64))
+}
+
+impl Cooldowns {
+    /// Whether the user or the guild as a whole is on cooldown.
+    pub fn is_guild_or_user_on_cooldown(&self, guild_id: GuildId, user_id: UserId) -> bool {
+        self.is_guild_or_user_on_cooldown_at(guild_id, user_id, Instant::now())
+    }
+
+    fn is_guild_or_user_on_cooldown_at(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        now: Instant,
+    ) -> bool {
+        self.guild.get(&guild_id).is_some_and(|until| *until > now)
+            || self
+                .user
+                .get(&(guild_id, user_id))
+                .is_some_and(|until| *until > now)
+    }
+
+    pub fn is_joke_on_cooldown(&self, joke_name: &str, channel_id: ChannelId) -> bool {
+        self.is_joke_on_cooldown_at(joke_name, channel_id, Instant::now())
+    }
+
+    fn is_joke_on_cooldown_at(&self, joke_name: &str, channel_id: ChannelId, now: Instant) -> bool {
+        self.joke
+            .get(&(joke_name.to_string(), channel_id))
+            .is_some_and(|until| *until > now)
+    }
+
+    /// Starts all configured cooldowns after a joke was made.
+    pub fn trigger(
+        &mut self,
+        config: &JokeConfig,
+        joke_name: &str,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) {
+        self.trigger_at(
+            config,
+            joke_name,
+            guild_id,
+            channel_id,
+            user_id,
+            Instant::now(),
+        );
+    }
+
+    fn trigger_at(
+        &mut self,
+        config: &JokeConfig,
+        joke_name: &str,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        user_id: UserId,
+        now: Instant,
+    ) {
+        // forget expired cooldowns, so the maps don't grow forever
+        self.joke.retain(|_, until| *until > now);
+        self.user.retain(|_, until| *until > now);
+        self.guild.retain(|_, until| *until > now);
+
+        if let Some(cooldown) = seconds(config.joke_cooldown) {
+            self.joke
+                .insert((joke_name.to_string(), channel_id), now + cooldown);
+        }
+        if let Some(cooldown) = seconds(config.user_cooldown) {
+            self.user.insert((guild_id, user_id), now + cooldown);
+        }
+        if let Some(cooldown) = seconds(config.guild_cooldown) {
+            self.guild.insert(guild_id, now + cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: GuildId = GuildId::new(1);
+    const CHANNEL: ChannelId = ChannelId::new(2);
+    const OTHER_CHANNEL: ChannelId = ChannelId::new(3);
+    const USER: UserId = UserId::new(4);
+    const OTHER_USER: UserId = UserId::new(5);
+
+    fn after(now: Instant, seconds: u64) -> Instant {
+        now + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn joke_cooldown_is_per_joke_and_channel() {
+        let config = JokeConfig {
+            joke_cooldown: Some(60),
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut cooldowns = Cooldowns::default();
+        cooldowns.trigger_at(&config, "joke", GUILD, CHANNEL, USER, now);
+
+        assert!(cooldowns.is_joke_on_cooldown_at("joke", CHANNEL, after(now, 59)));
+        assert!(!cooldowns.is_joke_on_cooldown_at("joke", CHANNEL, after(now, 60)));
+        assert!(!cooldowns.is_joke_on_cooldown_at("other", CHANNEL, now));
+        assert!(!cooldowns.is_joke_on_cooldown_at("joke", OTHER_CHANNEL, now));
+        // only the joke is on cooldown
+        assert!(!cooldowns.is_guild_or_user_on_cooldown_at(GUILD, USER, now));
+    }
+
+    #[test]
+    fn user_cooldown_is_per_user() {
+        let config = JokeConfig {
+            user_cooldown: Some(30),
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut cooldowns = Cooldowns::default();
+        cooldowns.trigger_at(&config, "joke", GUILD, CHANNEL, USER, now);
+
+        assert!(cooldowns.is_guild_or_user_on_cooldown_at(GUILD, USER, after(now, 29)));
+        assert!(!cooldowns.is_guild_or_user_on_cooldown_at(GUILD, USER, after(now, 30)));
+        assert!(!cooldowns.is_guild_or_user_on_cooldown_at(GUILD, OTHER_USER, now));
+        assert!(!cooldowns.is_guild_or_user_on_cooldown_at(GuildId::new(9), USER, now));
+        assert!(!cooldowns.is_joke_on_cooldown_at("joke", CHANNEL, now));
+    }
+
+    #[test]
+    fn guild_cooldown_covers_everyone() {
+        let config = JokeConfig {
+            guild_cooldown: Some(10),
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut cooldowns = Cooldowns::default();
+        cooldowns.trigger_at(&config, "joke", GUILD, CHANNEL, USER, now);
+
+        assert!(cooldowns.is_guild_or_user_on_cooldown_at(GUILD, USER, after(now, 9)));
+        assert!(cooldowns.is_guild_or_user_on_cooldown_at(GUILD, OTHER_USER, after(now, 9)));
+        assert!(!cooldowns.is_guild_or_user_on_cooldown_at(GUILD, OTHER_USER, after(now, 10)));
+        assert!(!cooldowns.is_guild_or_user_on_cooldown_at(GuildId::new(9), USER, now));
+    }
+
+    #[test]
+    fn unset_or_zero_cooldowns_never_block() {
+        let config = JokeConfig {
+            joke_cooldown: Some(0),
+            user_cooldown: Some(-5),
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut cooldowns = Cooldowns::default();
+        cooldowns.trigger_at(&config, "joke", GUILD, CHANNEL, USER, now);
+
+        assert!(!cooldowns.is_joke_on_cooldown_at("joke", CHANNEL, now));
+        assert!(!cooldowns.is_guild_or_user_on_cooldown_at(GUILD, USER, now));
+    }
+
+    #[test]
+    fn expired_cooldowns_are_forgotten() {
+        let config = JokeConfig {
+            joke_cooldown: Some(5),
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut cooldowns = Cooldowns::default();
+        cooldowns.trigger_at(&config, "old", GUILD, CHANNEL, USER, now);
+        cooldowns.trigger_at(&config, "new", GUILD, CHANNEL, USER, after(now, 10));
+
+        assert_eq!(cooldowns.joke.len(), 1);
+        assert!(cooldowns.is_joke_on_cooldown_at("new", CHANNEL, after(now, 14)));
+        assert!(!cooldowns.is_joke_on_cooldown_at("new", CHANNEL, after(now, 15)));
+    }
+}
@@ -0,0 +1,136 @@
+use rand::Rng;
+
+use crate::{
+    config::Joke,
+    matcher::{JokeMatcher, TriggerEvent},
+    template::{CaptureValues, TemplateContext},
+    MatchStrategy,
+};
+
+fn format_captures(captures: &CaptureValues) -> String {
+    let mut values: Vec<String> = captures
+        .numbered
+        .iter()
+        .enumerate()
+        // group 0 is the whole match
+        .skip(1)
+        .map(|(i, value)| match value {
+            Some(value) => format!("{}: `{}`", i, value),
+            None => format!("{}: none", i),
+        })
+        .collect();
+    let mut named: Vec<_> = captures.named.iter().collect();
+    named.sort();
+    values.extend(
+        named
+            .into_iter()
+            .map(|(name, value)| format!("{}: `{}`", name, value)),
+    );
+    if values.is_empty() {
+        "none".to_string()
+    } else {
+        values.join(", ")
+    }
+}
+
+fn format_response(joke: &Joke, context: TemplateContext, rng: &mut impl Rng) -> Vec<String> {
+    let mut lines = Vec::new();
+    match joke.choose_message(rng) {
+        Some(variant) => lines.push(format!(
+            "  Response: {}",
+            joke.message[variant].template.render(&context, rng)
+        )),
+        None if joke.response.sends_message() => lines.push("  Response without text".to_string()),
+        None => {}
+    }
+    if !joke.response.reactions.is_empty() {
+        let reactions: Vec<String> = joke
+            .response
+            .reactions
+            .iter()
+            .map(|reaction| reaction.to_string())
+            .collect();
+        lines.push(format!("  Reactions: {}", reactions.join(" ")));
+    }
+    if let Some(embed) = &joke.response.embed {
+        lines.push(format!(
+            "  Embed: {}",
+            embed.title.as_deref().unwrap_or("without title")
+        ));
+    }
+    if let Some(attachment) = &joke.response.attachment {
+        lines.push(format!("  Attachment: {}", attachment.display()));
+    }
+    if let Some(sticker) = joke.response.sticker {
+        lines.push(format!("  Sticker: {}", sticker));
+    }
+    if let Some(delay) = joke.delay {
+        lines.push(format!("  Delay: {}", delay));
+    }
+    if let Some(conversation) = &joke.conversation {
+        lines.push(format!(
+            "  Starts a conversation of {} more messages",
+            conversation.steps.len()
+        ));
+    }
+    lines
+}
+
+/// Describes which jokes would respond to the event and how, without sending anything.
+/// `allowed` tells for each joke of the matcher whether it can be made,
+/// so disabled jokes and jokes on cooldown are listed but don't respond.
+/// The chance is not rolled, the report shows what happens if it hits.
+pub fn dry_run(
+    matcher: &JokeMatcher,
+    event: &TriggerEvent,
+    allowed: &[bool],
+    strategy: MatchStrategy,
+    context: &TemplateContext,
+    rng: &mut impl Rng,
+) -> String {
+    let everything = vec![true; matcher.jokes().len()];
+    let matched = matcher.find(event, &everything, true);
+    if matched.is_empty() {
+        return "No joke matches".to_string();
+    }
+
+    let is_allowed = |joke: &Joke| {
+        matcher
+            .jokes()
+            .iter()
+            .position(|other| other.name == joke.name)
+            .is_some_and(|i| allowed[i])
+    };
+    // how many of the allowed matches respond in order
+    let limit = match strategy {
+        MatchStrategy::All { max } => max,
+        _ => 1,
+    };
+
+    let mut lines = vec![format!("Match strategy: {}", strategy)];
+    let mut responding = 0;
+    for (joke, captures) in matched {
+        let status = if !is_allowed(&joke) {
+            "disabled or on cooldown"
+        } else if strategy == MatchStrategy::Random {
+            "could respond, one match is picked at random"
+        } else if responding < limit {
+            responding += 1;
+            "would respond"
+        } else {
+            "matches, but isn't made"
+        };
+        lines.push(format!(
+            "- `{}` (priority {}): {}",
+            joke.name, joke.priority, status
+        ));
+        lines.push(format!("  Trigger: {}", joke.trigger));
+        lines.push(format!("  Captures: {}", format_captures(&captures)));
+        let context = TemplateContext {
+            captures,
+            ..context.clone()
+        };
+        lines.extend(format_response(&joke, context, rng));
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,159 @@
+use serenity::{
+    all::{ChannelId, GuildId, MessageId, MessageUpdateEvent},
+    client::Context,
+    model::channel::Message,
+};
+use tracing::{debug, error};
+
+use crate::{
+    config::Trigger,
+    get_joke_config, get_joke_matcher, handle_jokes_edited_message,
+    matcher::{match_trigger, TriggerEvent},
+    should_respond, State,
+};
+
+struct TriggeredJoke {
+    id: i64,
+    joke_name: String,
+    reply_message_id: Option<i64>,
+}
+
+/// Returns the jokes made about a message that are still tracked.
+async fn get_triggered_jokes(ctx: &Context, message_id: MessageId) -> Vec<TriggeredJoke> {
+    let message_id = message_id.get() as i64;
+    sqlx::query_as!(
+        TriggeredJoke,
+        r#"SELECT id AS "id!", joke_name, reply_message_id FROM JokeEvents WHERE trigger_message_id = $1"#,
+        message_id
+    )
+    .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap()
+}
+
+/// Deletes the message the bot sent for the joke, if any, and stops tracking its trigger.
+async fn remove_joke(ctx: &Context, channel_id: ChannelId, joke: &TriggeredJoke) {
+    if let Some(reply_message_id) = joke.reply_message_id {
+        let reply_message_id = MessageId::new(reply_message_id as u64);
+        if let Err(e) = channel_id.delete_message(&ctx.http, reply_message_id).await {
+            // the joke may have been deleted already, e.g. after downvotes
+            debug!("Could not delete joke {}: {:?}", reply_message_id, e);
+        }
+    }
+    if let Err(e) = sqlx::query!(
+        "UPDATE JokeEvents SET trigger_message_id = NULL WHERE id = $1",
+        joke.id
+    )
+    .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    {
+        error!("Error untracking joke event: {:?}", e);
+    }
+}
+
+/// Returns the edited message, from the cache or else from the event itself.
+/// Discord sends the whole message with an edit, so it only has to be fetched
+/// if the event is missing its author.
+async fn get_edited_message(
+    ctx: &Context,
+    new: Option<Message>,
+    event: &MessageUpdateEvent,
+) -> Option<Message> {
+    let mut msg = match new {
+        Some(msg) => msg,
+        None if event.author.is_some() => {
+            let mut msg = Message::default();
+            msg.id = event.id;
+            msg.channel_id = event.channel_id;
+            msg
+        }
+        None => match event.channel_id.message(&ctx.http, event.id).await {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("Error fetching edited message: {:?}", e);
+                return None;
+            }
+        },
+    };
+    event.apply_to_message(&mut msg);
+    Some(msg)
+}
+
+/// Checks an edited message again, if the guild enabled it.
+/// Jokes whose trigger no longer matches are deleted, and a message without jokes
+/// goes through the joke pipeline like a new one, without answering a conversation.
+pub async fn handle_jokes_message_update(
+    ctx: &Context,
+    new: Option<Message>,
+    update: &MessageUpdateEvent,
+) {
+    // only edits of the content are interesting
+    if update.content.is_none() {
+        return;
+    }
+    let Some(guild_id) = update.guild_id else {
+        return;
+    };
+    if !get_joke_config(ctx, guild_id)
+        .await
+        .evaluate_edits
+        .unwrap_or(false)
+    {
+        return;
+    }
+    let Some(msg) = get_edited_message(ctx, new, update).await else {
+        return;
+    };
+    if !should_respond(&msg) {
+        return;
+    }
+
+    let matcher = get_joke_matcher(ctx, guild_id).await;
+    let event = TriggerEvent::from_message(&msg, ctx.cache.current_user().id);
+    let mut still_joked = false;
+    for joke in get_triggered_jokes(ctx, msg.id).await {
+        let trigger = matcher
+            .jokes()
+            .iter()
+            .find(|other| other.name == joke.joke_name)
+            .map(|other| &other.trigger);
+        // reactions stay on the message, so jokes about them stay too
+        if matches!(trigger, Some(Trigger::Reaction(_))) {
+            continue;
+        }
+        // the roles of the author are unknown if the message had to be fetched over http
+        if matches!(trigger, Some(Trigger::Role(_))) && msg.member.is_none() {
+            still_joked = true;
+            continue;
+        }
+        let matches =
+            trigger.is_some_and(|trigger| matches!(match_trigger(trigger, &event), Ok(Some(_))));
+        if matches {
+            still_joked = true;
+        } else {
+            debug!("Removing joke {} after an edit", joke.joke_name);
+            remove_joke(ctx, msg.channel_id, &joke).await;
+        }
+    }
+
+    // don't make another joke about a message that still has one
+    if !still_joked {
+        handle_jokes_edited_message(ctx, &msg).await;
+    }
+}
+
+/// Deletes the jokes about a message when it is deleted.
+pub async fn handle_jokes_message_delete(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+) {
+    if guild_id.is_none() {
+        return;
+    }
+    for joke in get_triggered_jokes(ctx, message_id).await {
+        debug!("Removing joke {}, its trigger was deleted", joke.joke_name);
+        remove_joke(ctx, channel_id, &joke).await;
+    }
+}
@@ -1,86 +1,769 @@
-use serenity::{client::Context, model::channel::Message};
+use serenity::{
+    all::{
+        ChannelId, CreateAllowedMentions, CreateAttachment, CreateEmbed, CreateMessage, GuildId,
+        MessageId, Reaction, User,
+    },
+    client::Context,
+    model::channel::Message,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::error;
 
-use crate::{config::Config, State};
-use rand::prelude::SliceRandom;
+use crate::{
+    config::{Config, Delay, Joke, RawJoke, RawMessage, Trigger, DEFAULT_LOCALE},
+    handle_conversation_message,
+    matcher::{JokeMatcher, TriggerEvent},
+    should_respond, start_conversation,
+    template::{CaptureValues, TemplateContext},
+    MatchStrategy, State, GLOBAL_OPT_OUT,
+};
 
+#[derive(Default)]
 pub struct JokeConfig {
     pub chance: f64,
     pub guild_id: i64,
+    /// seconds before the same joke can be made in a channel again
+    pub joke_cooldown: Option<i64>,
+    /// seconds before the bot makes a joke about the same user again
+    pub user_cooldown: Option<i64>,
+    /// seconds before the bot makes any joke in the guild again
+    pub guild_cooldown: Option<i64>,
+    pub match_strategy: Option<String>,
+    pub max_matches: Option<i64>,
+    /// downvotes after which a joke message is deleted, `DEFAULT_DOWNVOTES_TO_DELETE` if not set
+    pub downvotes_to_delete: Option<i64>,
+    /// ratio of downvoted messages above which the chance of a joke is lowered
+    pub auto_lower_threshold: Option<f64>,
+    /// whether edited messages are checked for jokes again
+    pub evaluate_edits: Option<bool>,
+    /// seconds to show the typing indicator before a joke
+    pub delay_min: Option<f64>,
+    pub delay_max: Option<f64>,
+    /// locale of the jokes, the guild's preferred locale if not set
+    pub locale: Option<String>,
 }
 
-pub async fn handle_jokes_message(ctx: &Context, msg: &Message) {
-    // check db for chance of making a joke
-    let gid = msg.guild_id.unwrap().get() as i64;
-    let chance = sqlx::query_as!(
+impl JokeConfig {
+    pub fn match_strategy(&self) -> MatchStrategy {
+        MatchStrategy::from_config(self.match_strategy.as_deref(), self.max_matches)
+    }
+
+    pub fn delay(&self) -> Option<Delay> {
+        Delay::from_config(self.delay_min, self.delay_max)
+    }
+}
+
+/// Chance stored for guilds that never configured one, i.e. always make jokes.
+pub const DEFAULT_CHANCE: f64 = 1.0;
+
+/// Returns the config of a guild, storing the default config on first use.
+pub async fn get_joke_config(ctx: &Context, guild_id: GuildId) -> JokeConfig {
+    let gid = guild_id.get() as i64;
+    let config = sqlx::query_as!(
         JokeConfig,
         "SELECT * FROM JokeConfig WHERE guild_id = $1",
         gid
     )
     .fetch_optional(&ctx.data.read().await.get::<State>().unwrap().pool)
     .await
-    .unwrap()
-    .unwrap_or(JokeConfig {
-        chance: 1.1,
+    .unwrap();
+    if let Some(config) = config {
+        return config;
+    }
+
+    sqlx::query!(
+        "INSERT INTO JokeConfig (guild_id, chance) VALUES ($1, $2) ON CONFLICT (guild_id) DO NOTHING",
+        gid,
+        DEFAULT_CHANCE
+    )
+    .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap();
+    JokeConfig {
+        chance: DEFAULT_CHANCE,
         guild_id: gid,
-    });
+        joke_cooldown: None,
+        user_cooldown: None,
+        guild_cooldown: None,
+        match_strategy: None,
+        max_matches: None,
+        downvotes_to_delete: None,
+        auto_lower_threshold: None,
+        evaluate_edits: None,
+        delay_min: None,
+        delay_max: None,
+        locale: None,
+    }
+}
 
-    if rand::random::<f64>() > chance.chance {
-        return;
+/// Returns the locale of the jokes made in a guild.
+/// Falls back to the guild's preferred locale, `None` if the guild isn't cached yet.
+pub async fn get_guild_locale(ctx: &Context, guild_id: GuildId) -> Option<String> {
+    if let Some(locale) = get_joke_config(ctx, guild_id).await.locale {
+        return Some(locale);
     }
+    ctx.cache
+        .guild(guild_id)
+        .map(|guild| guild.preferred_locale.clone())
+}
 
-    let jokes = ctx.data.read().await.get::<Config>().unwrap().jokes.clone();
+/// Per guild settings for a single joke, as stored in the `JokeOverride` table.
+pub struct JokeOverride {
+    pub guild_id: i64,
+    pub joke_name: String,
+    pub enabled: bool,
+    pub chance: Option<f64>,
+}
 
-    for joke in jokes {
-        if let Some(servers) = joke.servers {
-            if !servers.contains(&msg.guild_id.unwrap()) {
-                continue;
-            }
+/// Settings for a channel or category, as stored in the `JokeChannelConfig` table.
+pub struct JokeChannelConfig {
+    pub channel_id: i64,
+    pub guild_id: i64,
+    pub enabled: bool,
+    pub chance: Option<f64>,
+}
+
+/// A joke added by a guild admin, as stored in the `Jokes` table.
+pub struct GuildJoke {
+    pub guild_id: i64,
+    pub name: String,
+    pub regex: String,
+    /// json array of message variants
+    pub message: String,
+    pub reply: bool,
+    pub enabled: bool,
+    pub priority: i64,
+}
+
+impl GuildJoke {
+    pub fn messages(&self) -> Vec<String> {
+        serde_json::from_str(&self.message).unwrap_or_default()
+    }
+
+    pub fn to_raw(&self) -> RawJoke {
+        RawJoke {
+            name: self.name.clone(),
+            servers: Some(vec![GuildId::new(self.guild_id as u64)]),
+            regex: Some(self.regex.clone()),
+            message: self.messages().into_iter().map(RawMessage::from).collect(),
+            reply: Some(self.reply),
+            priority: self.priority,
+            ..Default::default()
         }
-        let matches = joke.regex.captures(&msg.content).unwrap();
-        if let Some(matches) = matches {
-            // replace --[number]-- with the corresponding capture group
-            let mut message = joke
-                .message
-                .choose(&mut rand::thread_rng())
-                .unwrap()
-                .to_owned();
-            for (i, capture) in matches.iter().enumerate() {
-                if let Some(capture) = capture {
-                    message = message.replace(&format!("--[{}]--", i), capture.as_str());
+    }
+}
+
+/// Returns the enabled jokes of a guild, compiling and caching them on first use.
+pub async fn get_guild_jokes(ctx: &Context, guild_id: GuildId) -> Vec<Joke> {
+    if let Some(jokes) = ctx
+        .data
+        .read()
+        .await
+        .get::<State>()
+        .unwrap()
+        .guild_jokes
+        .get(&guild_id)
+    {
+        return jokes.clone();
+    }
+
+    let gid = guild_id.get() as i64;
+    let rows = sqlx::query_as!(
+        GuildJoke,
+        "SELECT guild_id, name, regex, message, reply, enabled, priority FROM Jokes WHERE guild_id = $1 AND enabled = TRUE",
+        gid
+    )
+    .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap();
+
+    let jokes: Vec<Joke> = rows
+        .iter()
+        .filter_map(|row| match row.to_raw().compile() {
+            Ok(joke) => Some(joke),
+            Err(errors) => {
+                for e in errors {
+                    error!("Invalid joke in guild {}: {}", guild_id, e);
                 }
+                None
             }
-            // replace --[nickname]-- with the nickname of the user
-            let nickname = msg
-                .guild_id
-                .unwrap()
-                .member(&ctx.http, msg.author.id)
-                .await
-                .unwrap()
-                .nick
-                .unwrap_or(msg.author.name.clone());
-            message = message.replace("--[nickname]--", &nickname);
-            // replace --[username]-- with the username of the user
-            message = message.replace("--[username]--", &msg.author.name);
-            // replace --[guild]-- with the name of the guild
-            let guild = msg
-                .guild_id
-                .unwrap()
-                .to_partial_guild(&ctx.http)
+        })
+        .collect();
+
+    let mut data = ctx.data.write().await;
+    let state = data.get_mut::<State>().unwrap();
+    state.guild_jokes.insert(guild_id, jokes.clone());
+    jokes
+}
+
+/// Returns all jokes that can be made in a guild with the locale, leaving out jokes in other languages.
+/// Guild jokes come first, so they are not shadowed by the global ones.
+pub async fn get_available_jokes(ctx: &Context, guild_id: GuildId, locale: &str) -> Vec<Joke> {
+    let mut jokes = get_guild_jokes(ctx, guild_id).await;
+    jokes.extend(
+        ctx.data
+            .read()
+            .await
+            .get::<Config>()
+            .unwrap()
+            .jokes
+            .iter()
+            .filter(|joke| {
+                joke.servers
+                    .as_ref()
+                    .is_none_or(|servers| servers.contains(&guild_id))
+                    && joke.is_for_locale(locale)
+            })
+            .cloned(),
+    );
+    jokes
+}
+
+pub async fn get_joke_overrides(ctx: &Context, guild_id: GuildId) -> HashMap<String, JokeOverride> {
+    let gid = guild_id.get() as i64;
+    sqlx::query_as!(
+        JokeOverride,
+        "SELECT guild_id, joke_name, enabled, chance FROM JokeOverride WHERE guild_id = $1",
+        gid
+    )
+    .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|joke_override| (joke_override.joke_name.clone(), joke_override))
+    .collect()
+}
+
+/// Returns the rule for the channel, falling back to the rules of its parents
+/// (the channel of a thread, then the category) if the channel has none.
+pub async fn get_channel_rule(ctx: &Context, channel_id: ChannelId) -> Option<JokeChannelConfig> {
+    // a thread or forum post inherits from its channel, a channel from its category
+    let mut channels = vec![channel_id];
+    while channels.len() < 3 {
+        let parent_id = channels
+            .last()
+            .unwrap()
+            .to_channel(&ctx)
+            .await
+            .ok()
+            .and_then(|channel| channel.guild())
+            .and_then(|channel| channel.parent_id);
+        match parent_id {
+            Some(parent_id) => channels.push(parent_id),
+            None => break,
+        }
+    }
+    let ids: Vec<i64> = channels
+        .iter()
+        .map(|channel| channel.get() as i64)
+        .collect();
+    let (channel_id, parent_id, grandparent_id) = (ids[0], ids.get(1), ids.get(2));
+
+    let mut rules = sqlx::query_as!(
+        JokeChannelConfig,
+        "SELECT channel_id, guild_id, enabled, chance FROM JokeChannelConfig WHERE channel_id = $1 OR channel_id = $2 OR channel_id = $3",
+        channel_id,
+        parent_id,
+        grandparent_id
+    )
+    .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap();
+
+    // the rule of the closest channel takes precedence
+    rules.sort_by_key(|rule| ids.iter().position(|id| *id == rule.channel_id));
+    rules.into_iter().next()
+}
+
+/// Whether the author of the message opted out of jokes in this guild or globally.
+pub async fn has_opted_out(ctx: &Context, msg: &Message) -> bool {
+    let user_id = msg.author.id.get() as i64;
+    // only the global opt-out applies to direct messages
+    let guild_id = msg
+        .guild_id
+        .map_or(GLOBAL_OPT_OUT, |guild_id| guild_id.get() as i64);
+    sqlx::query!(
+        "SELECT user_id FROM JokeOptOut WHERE user_id = $1 AND (guild_id = $2 OR guild_id = $3)",
+        user_id,
+        guild_id,
+        GLOBAL_OPT_OUT
+    )
+    .fetch_optional(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap()
+    .is_some()
+}
+
+/// Collects the values a joke message can refer to.
+pub async fn template_context(ctx: &Context, msg: &Message) -> TemplateContext {
+    user_template_context(ctx, msg.guild_id, msg.channel_id, &msg.author).await
+}
+
+/// Collects the values a joke message can refer to when `user` writes in the channel.
+/// Values that can't be fetched, e.g. the guild in direct messages, are left empty.
+pub async fn user_template_context(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    user: &User,
+) -> TemplateContext {
+    let (nickname, guild) = match guild_id {
+        Some(guild_id) => (
+            guild_id
+                .member(&ctx.http, user.id)
                 .await
-                .unwrap();
-            message = message.replace("--[guild]--", &guild.name);
-            // replace --[channel]-- with the name of the channel
-            let channel = msg.channel_id.to_channel(&ctx.http).await.unwrap();
-            message = message.replace(
-                "--[channel]--",
-                &channel.guild().map(|c| c.name).unwrap_or("DM".to_string()),
-            );
-            if joke.reply.unwrap_or(false) {
-                msg.reply_ping(&ctx.http, message).await.unwrap();
-            } else {
-                msg.channel_id.say(&ctx.http, message).await.unwrap();
+                .ok()
+                .and_then(|member| member.nick),
+            guild_id.to_partial_guild(&ctx.http).await.ok(),
+        ),
+        None => (None, None),
+    };
+    let channel = channel_id
+        .to_channel(&ctx.http)
+        .await
+        .ok()
+        .and_then(|channel| channel.guild())
+        .map(|channel| channel.name)
+        .unwrap_or("DM".to_string());
+    let emojis = guild
+        .iter()
+        .flat_map(|guild| guild.emojis.values())
+        .map(|emoji| (emoji.name.clone(), emoji.to_string()))
+        .collect();
+
+    TemplateContext {
+        nickname: nickname.unwrap_or(user.name.clone()),
+        username: user.name.clone(),
+        guild: guild.map(|guild| guild.name).unwrap_or_default(),
+        channel,
+        user_id: Some(user.id),
+        channel_id: Some(channel_id),
+        emojis,
+        ..Default::default()
+    }
+}
+
+/// Sends the response of a joke, `message` being the rendered text if the joke has any.
+/// Returns the message the bot sent, if any.
+pub async fn send_joke_response(
+    ctx: &Context,
+    msg: &Message,
+    joke: &Joke,
+    message: Option<String>,
+) -> Option<Message> {
+    for reaction in &joke.response.reactions {
+        if let Err(e) = msg.react(&ctx.http, reaction.clone()).await {
+            error!("Error reacting to message: {:?}", e);
+        }
+    }
+    if !joke.response.sends_message() {
+        return None;
+    }
+
+    let mut builder = CreateMessage::new();
+    match &joke.response.embed {
+        Some(embed) => {
+            let mut create_embed = CreateEmbed::new();
+            if let Some(message) = message {
+                create_embed = create_embed.description(message);
+            }
+            if let Some(title) = &embed.title {
+                create_embed = create_embed.title(title);
+            }
+            if let Some(image) = &embed.image {
+                create_embed = create_embed.image(image);
             }
-            break;
+            builder = builder.embed(create_embed);
+        }
+        None => {
+            if let Some(message) = message {
+                builder = builder.content(message);
+            }
+        }
+    }
+    if let Some(attachment) = &joke.response.attachment {
+        match CreateAttachment::path(attachment).await {
+            Ok(attachment) => builder = builder.add_file(attachment),
+            Err(e) => error!("Error reading attachment {:?}: {:?}", attachment, e),
+        }
+    }
+    if let Some(sticker) = joke.response.sticker {
+        builder = builder.add_sticker_id(sticker);
+    }
+    if joke.reply.unwrap_or(false) {
+        // same as `reply_ping`
+        builder = builder.reference_message(msg).allowed_mentions(
+            CreateAllowedMentions::new()
+                .replied_user(true)
+                .everyone(true)
+                .all_users(true)
+                .all_roles(true),
+        );
+    }
+    match msg.channel_id.send_message(&ctx.http, builder).await {
+        Ok(reply) => Some(reply),
+        Err(e) => {
+            error!("Error sending joke: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Stores that a joke was made in `JokeEvents`, for `/joke stats`.
+pub async fn record_joke_event(
+    ctx: &Context,
+    msg: &Message,
+    joke_name: &str,
+    variant: Option<usize>,
+    reply: Option<&Message>,
+) {
+    // stats are per guild, so jokes in direct messages aren't recorded
+    let Some(guild_id) = msg.guild_id else {
+        return;
+    };
+    let guild_id = guild_id.get() as i64;
+    let channel_id = msg.channel_id.get() as i64;
+    let user_id = msg.author.id.get() as i64;
+    let variant = variant.map(|variant| variant as i64);
+    let reply_message_id = reply.map(|reply| reply.id.get() as i64);
+    let trigger_message_id = msg.id.get() as i64;
+    if let Err(e) = sqlx::query!(
+        "INSERT INTO JokeEvents (guild_id, channel_id, user_id, joke_name, variant, reply_message_id, trigger_message_id) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        guild_id,
+        channel_id,
+        user_id,
+        joke_name,
+        variant,
+        reply_message_id,
+        trigger_message_id
+    )
+    .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    {
+        error!("Error recording joke event: {:?}", e);
+    }
+}
+
+/// Whether the joke was already made about the message, according to `JokeEvents`.
+async fn joke_made_about(ctx: &Context, message_id: MessageId, joke_name: &str) -> bool {
+    let trigger_message_id = message_id.get() as i64;
+    sqlx::query!(
+        "SELECT id FROM JokeEvents WHERE trigger_message_id = $1 AND joke_name = $2 LIMIT 1",
+        trigger_message_id,
+        joke_name
+    )
+    .fetch_optional(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap()
+    .is_some()
+}
+
+/// Drops the cached jokes of a guild, so they get reloaded from the database.
+pub async fn invalidate_guild_jokes(ctx: &Context, guild_id: GuildId) {
+    let mut data = ctx.data.write().await;
+    let state = data.get_mut::<State>().unwrap();
+    state.guild_jokes.remove(&guild_id);
+    state.matchers.remove(&guild_id);
+}
+
+/// Returns the matcher for the jokes available in a guild, building and caching it on first use.
+/// The jokes are sorted by priority, the sort is stable,
+/// so guild jokes stay ahead of global ones with the same priority.
+pub async fn get_joke_matcher(ctx: &Context, guild_id: GuildId) -> Arc<JokeMatcher> {
+    if let Some(matcher) = ctx
+        .data
+        .read()
+        .await
+        .get::<State>()
+        .unwrap()
+        .matchers
+        .get(&guild_id)
+    {
+        return matcher.clone();
+    }
+
+    let locale = get_guild_locale(ctx, guild_id).await;
+    let mut jokes =
+        get_available_jokes(ctx, guild_id, locale.as_deref().unwrap_or(DEFAULT_LOCALE)).await;
+    jokes.sort_by_key(|joke| std::cmp::Reverse(joke.priority));
+    let matcher = Arc::new(JokeMatcher::new(jokes));
+
+    // without the locale the matcher is built again, once the guild is cached
+    if locale.is_some() {
+        let mut data = ctx.data.write().await;
+        let state = data.get_mut::<State>().unwrap();
+        state.matchers.insert(guild_id, matcher.clone());
+    }
+    matcher
+}
+
+/// Tells for each joke whether it can be made in the channel,
+/// i.e. it isn't disabled in the guild and not on cooldown.
+pub async fn allowed_jokes(
+    ctx: &Context,
+    jokes: &[Joke],
+    overrides: &HashMap<String, JokeOverride>,
+    channel_id: ChannelId,
+) -> Vec<bool> {
+    let data = ctx.data.read().await;
+    let cooldowns = &data.get::<State>().unwrap().cooldowns;
+    jokes
+        .iter()
+        .map(|joke| {
+            let disabled = overrides
+                .get(&joke.name)
+                .is_some_and(|joke_override| !joke_override.enabled);
+            !disabled && !cooldowns.is_joke_on_cooldown(&joke.name, channel_id)
+        })
+        .collect()
+}
+
+/// Returns the matcher for the jokes about direct messages, building and caching it on first use.
+pub async fn get_dm_joke_matcher(ctx: &Context) -> Arc<JokeMatcher> {
+    if let Some(matcher) = &ctx.data.read().await.get::<State>().unwrap().dm_matcher {
+        return matcher.clone();
+    }
+
+    let jokes = ctx
+        .data
+        .read()
+        .await
+        .get::<Config>()
+        .unwrap()
+        .direct_messages
+        .jokes
+        .clone();
+    let matcher = Arc::new(JokeMatcher::new(jokes));
+
+    let mut data = ctx.data.write().await;
+    let state = data.get_mut::<State>().unwrap();
+    state.dm_matcher = Some(matcher.clone());
+    matcher
+}
+
+/// Renders the joke, waits for the delay and sends the response.
+pub async fn make_joke(
+    ctx: &Context,
+    msg: &Message,
+    joke: &Joke,
+    matches: CaptureValues,
+    delay: Option<Delay>,
+) {
+    let variant = joke.choose_message(&mut rand::thread_rng());
+    let message = match variant {
+        Some(variant) => {
+            let context = template_context(ctx, msg).await.with_captures(matches);
+            Some(
+                joke.message[variant]
+                    .template
+                    .render(&context, &mut rand::thread_rng()),
+            )
+        }
+        None => None,
+    };
+    // every event is handled in its own task, so waiting here doesn't hold up other events
+    if let Some(delay) = delay {
+        let delay = delay.sample(&mut rand::thread_rng());
+        if joke.response.sends_message() {
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            tokio::time::sleep(delay).await;
+            typing.stop();
+        } else {
+            tokio::time::sleep(delay).await;
+        }
+    }
+    let reply = send_joke_response(ctx, msg, joke, message).await;
+    record_joke_event(ctx, msg, &joke.name, variant, reply.as_ref()).await;
+}
+
+pub async fn handle_jokes_message(ctx: &Context, msg: &Message) {
+    let event = TriggerEvent::from_message(msg, ctx.cache.current_user().id);
+    handle_jokes_event(ctx, msg, event, true).await;
+}
+
+/// Makes jokes about an edited message, which can't answer a conversation,
+/// since the author's next message is the one the conversation waits for.
+pub async fn handle_jokes_edited_message(ctx: &Context, msg: &Message) {
+    let event = TriggerEvent::from_message(msg, ctx.cache.current_user().id);
+    handle_jokes_event(ctx, msg, event, false).await;
+}
+
+/// Makes jokes about a message when a reaction is added to it.
+pub async fn handle_jokes_reaction(ctx: &Context, reaction: &Reaction) {
+    // the bot reacts to messages itself, which must not trigger more jokes
+    if reaction.user_id == Some(ctx.cache.current_user().id)
+        || reaction
+            .member
+            .as_ref()
+            .is_some_and(|member| member.user.bot)
+    {
+        return;
+    }
+    // reactions in direct messages aren't received, the bot doesn't request that intent
+    let Some(guild_id) = reaction.guild_id else {
+        return;
+    };
+    // only fetch the message if a joke is about the emoji
+    let matcher = get_joke_matcher(ctx, guild_id).await;
+    let reaction_jokes: Vec<&str> = matcher
+        .jokes()
+        .iter()
+        .filter(
+            |joke| matches!(&joke.trigger, Trigger::Reaction(emoji) if *emoji == reaction.emoji),
+        )
+        .map(|joke| joke.name.as_str())
+        .collect();
+    if reaction_jokes.is_empty() {
+        return;
+    }
+    // everyone reacting with the same emoji shouldn't get the joke again
+    for joke_name in reaction_jokes {
+        if joke_made_about(ctx, reaction.message_id, joke_name).await {
+            return;
+        }
+    }
+    let mut msg = match reaction.message(&ctx.http).await {
+        Ok(msg) => msg,
+        Err(e) => {
+            error!("Error fetching reacted message: {:?}", e);
+            return;
+        }
+    };
+    // messages fetched over http don't include the guild
+    msg.guild_id = Some(guild_id);
+    // e.g. a reaction to a joke of the bot
+    if !should_respond(&msg) {
+        return;
+    }
+    let event = TriggerEvent::from_reaction(reaction.emoji.clone());
+    handle_jokes_event(ctx, &msg, event, false).await;
+}
+
+/// Runs the jokes about the message through the pipeline for the event,
+/// `new_message` is whether the message can answer a conversation.
+async fn handle_jokes_event(ctx: &Context, msg: &Message, event: TriggerEvent, new_message: bool) {
+    let max_message_length = ctx
+        .data
+        .read()
+        .await
+        .get::<Config>()
+        .unwrap()
+        .max_message_length;
+    if msg.content.chars().count() > max_message_length {
+        return;
+    }
+
+    if has_opted_out(ctx, msg).await {
+        // a conversation started before the opt-out doesn't go on either
+        ctx.data
+            .write()
+            .await
+            .get_mut::<State>()
+            .unwrap()
+            .conversations
+            .take(msg.channel_id, msg.author.id);
+        return;
+    }
+
+    // answering a conversation takes precedence over starting a new joke
+    if new_message && handle_conversation_message(ctx, msg).await {
+        return;
+    }
+
+    match msg.guild_id {
+        Some(guild_id) => handle_guild_jokes_message(ctx, msg, guild_id, event).await,
+        None => handle_dm_jokes_message(ctx, msg, event).await,
+    }
+}
+
+/// Makes jokes about direct messages, if they are enabled in the config.
+async fn handle_dm_jokes_message(ctx: &Context, msg: &Message, event: TriggerEvent) {
+    let (enabled, chance) = {
+        let data = ctx.data.read().await;
+        let direct_messages = &data.get::<Config>().unwrap().direct_messages;
+        (direct_messages.enabled, direct_messages.chance)
+    };
+    if !enabled {
+        return;
+    }
+
+    let matcher = get_dm_joke_matcher(ctx).await;
+    let allowed = vec![true; matcher.jokes().len()];
+    let matched = tokio::task::spawn_blocking(move || matcher.find(&event, &allowed, false))
+        .await
+        .unwrap();
+
+    for (joke, matches) in matched {
+        if rand::random::<f64>() >= chance {
+            continue;
+        }
+        start_conversation(ctx, msg, &joke, &matches).await;
+        make_joke(ctx, msg, &joke, matches, joke.delay).await;
+    }
+}
+
+async fn handle_guild_jokes_message(
+    ctx: &Context,
+    msg: &Message,
+    guild_id: GuildId,
+    event: TriggerEvent,
+) {
+    let config = get_joke_config(ctx, guild_id).await;
+
+    if ctx
+        .data
+        .read()
+        .await
+        .get::<State>()
+        .unwrap()
+        .cooldowns
+        .is_guild_or_user_on_cooldown(guild_id, msg.author.id)
+    {
+        return;
+    }
+
+    // the channel rule replaces the guild chance, a joke override replaces both
+    let channel_rule = get_channel_rule(ctx, msg.channel_id).await;
+    if channel_rule.as_ref().is_some_and(|rule| !rule.enabled) {
+        return;
+    }
+    let chance = channel_rule
+        .and_then(|rule| rule.chance)
+        .unwrap_or(config.chance);
+
+    let matcher = get_joke_matcher(ctx, guild_id).await;
+    let overrides = get_joke_overrides(ctx, guild_id).await;
+    let strategy = config.match_strategy();
+
+    let allowed = allowed_jokes(ctx, matcher.jokes(), &overrides, msg.channel_id).await;
+
+    // backtracking regexes can take a while, so don't block the executor with them
+    let matched = tokio::task::spawn_blocking(move || {
+        matcher.find(&event, &allowed, strategy.needs_all_matches())
+    })
+    .await
+    .unwrap();
+
+    let selected = strategy.select(matched, &mut rand::thread_rng());
+    for (joke, matches) in selected {
+        // roll only once a joke matched, so the chance is the probability of responding
+        let chance = overrides
+            .get(&joke.name)
+            .and_then(|joke_override| joke_override.chance)
+            .unwrap_or(chance);
+        if rand::random::<f64>() >= chance {
+            continue;
+        }
+        // start the cooldowns right away, so the joke isn't made again while waiting
+        {
+            let mut data = ctx.data.write().await;
+            let state = data.get_mut::<State>().unwrap();
+            state
+                .cooldowns
+                .trigger(&config, &joke.name, guild_id, msg.channel_id, msg.author.id);
         }
+        start_conversation(ctx, msg, &joke, &matches).await;
+        make_joke(ctx, msg, &joke, matches, joke.delay.or(config.delay())).await;
     }
 }
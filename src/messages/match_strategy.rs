@@ -0,0 +1,166 @@
+use rand::{seq::IteratorRandom, Rng};
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of jokes made for one message with `MatchStrategy::All`, if the guild set no limit.
+pub const DEFAULT_MAX_MATCHES: usize = 3;
+
+/// How to pick the jokes to make if several jokes match the same message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchStrategy {
+    /// only the matching joke with the highest priority
+    First,
+    /// one of the matching jokes, chosen at random
+    Random,
+    /// every matching joke in order of priority, up to `max`
+    All { max: usize },
+}
+
+impl MatchStrategy {
+    pub fn from_config(strategy: Option<&str>, max_matches: Option<i64>) -> MatchStrategy {
+        match strategy.and_then(|strategy| strategy.parse().ok()) {
+            Some(MatchStrategy::All { .. }) => MatchStrategy::All {
+                max: max_matches
+                    .and_then(|max| usize::try_from(max).ok())
+                    .unwrap_or(DEFAULT_MAX_MATCHES),
+            },
+            Some(strategy) => strategy,
+            None => MatchStrategy::First,
+        }
+    }
+
+    /// Whether every joke has to be checked, or the first match is enough.
+    pub fn needs_all_matches(self) -> bool {
+        self != MatchStrategy::First
+    }
+
+    /// Picks the jokes to make from `matches`, which are sorted by priority.
+    pub fn select<T>(self, mut matches: Vec<T>, rng: &mut impl Rng) -> Vec<T> {
+        match self {
+            MatchStrategy::First => {
+                matches.truncate(1);
+                matches
+            }
+            MatchStrategy::Random => match (0..matches.len()).choose(rng) {
+                Some(i) => vec![matches.swap_remove(i)],
+                None => Vec::new(),
+            },
+            MatchStrategy::All { max } => {
+                matches.truncate(max);
+                matches
+            }
+        }
+    }
+}
+
+impl FromStr for MatchStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(MatchStrategy::First),
+            "random" => Ok(MatchStrategy::Random),
+            "all" => Ok(MatchStrategy::All {
+                max: DEFAULT_MAX_MATCHES,
+            }),
+            s => Err(format!("unknown match strategy `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for MatchStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchStrategy::First => write!(f, "first by priority"),
+            MatchStrategy::Random => write!(f, "random"),
+            MatchStrategy::All { max } => write!(f, "all, up to {}", max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn first_keeps_the_highest_priority() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(
+            MatchStrategy::First.select(vec![3, 2, 1], &mut rng),
+            vec![3]
+        );
+        assert_eq!(
+            MatchStrategy::First.select(Vec::<i32>::new(), &mut rng),
+            Vec::<i32>::new()
+        );
+    }
+
+    #[test]
+    fn random_picks_one_match() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut picked = Vec::new();
+        for _ in 0..50 {
+            let selected = MatchStrategy::Random.select(vec![1, 2, 3], &mut rng);
+            assert_eq!(selected.len(), 1);
+            picked.push(selected[0]);
+        }
+        picked.sort();
+        picked.dedup();
+        assert_eq!(picked, vec![1, 2, 3]);
+        assert!(MatchStrategy::Random
+            .select(Vec::<i32>::new(), &mut rng)
+            .is_empty());
+
+        // the same seed picks the same jokes
+        let pick = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..10)
+                .map(|_| MatchStrategy::Random.select(vec![1, 2, 3, 4], &mut rng)[0])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pick(42), pick(42));
+    }
+
+    #[test]
+    fn all_truncates_to_max() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(
+            MatchStrategy::All { max: 2 }.select(vec![5, 4, 3], &mut rng),
+            vec![5, 4]
+        );
+        assert_eq!(
+            MatchStrategy::All { max: 5 }.select(vec![5, 4, 3], &mut rng),
+            vec![5, 4, 3]
+        );
+    }
+
+    #[test]
+    fn from_config_falls_back() {
+        assert_eq!(MatchStrategy::from_config(None, None), MatchStrategy::First);
+        assert_eq!(
+            MatchStrategy::from_config(Some("unknown"), Some(5)),
+            MatchStrategy::First
+        );
+        assert_eq!(
+            MatchStrategy::from_config(Some("random"), Some(5)),
+            MatchStrategy::Random
+        );
+        assert_eq!(
+            MatchStrategy::from_config(Some("all"), None),
+            MatchStrategy::All {
+                max: DEFAULT_MAX_MATCHES
+            }
+        );
+        assert_eq!(
+            MatchStrategy::from_config(Some("all"), Some(-1)),
+            MatchStrategy::All {
+                max: DEFAULT_MAX_MATCHES
+            }
+        );
+        assert_eq!(
+            MatchStrategy::from_config(Some("all"), Some(5)),
+            MatchStrategy::All { max: 5 }
+        );
+    }
+}
@@ -1,5 +1,15 @@
+pub mod conversations;
+pub mod cooldowns;
+pub mod dry_run;
+pub mod edits;
 pub mod jokes;
+pub mod match_strategy;
 pub mod owner;
 
+pub use conversations::*;
+pub use cooldowns::*;
+pub use dry_run::*;
+pub use edits::*;
 pub use jokes::*;
-pub use owner::*;
\ No newline at end of file
+pub use match_strategy::*;
+pub use owner::*;
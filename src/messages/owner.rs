@@ -1,6 +1,19 @@
+use serde::{Deserialize, Serialize};
 use serenity::{client::Context, model::channel::Message};
 
-use crate::{State, UserIDGuildID};
+use crate::{config, Config, State, UserIDGuildID, VcPingChannel};
+
+/// The ping list as written by `$export` and read by `$import`.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum PingListExport {
+    PingList {
+        users: Vec<UserIDGuildID>,
+        channels: Vec<VcPingChannel>,
+    },
+    /// exports from before the channels were added
+    Users(Vec<UserIDGuildID>),
+}
 
 pub async fn handle_owner_message(ctx: &Context, msg: &Message) {
     if msg.content == "$export" {
@@ -13,8 +26,17 @@ pub async fn handle_owner_message(ctx: &Context, msg: &Message) {
                 .fetch_all(pool)
                 .await
                 .unwrap();
+        let vc_ping_channels: Vec<VcPingChannel> =
+            sqlx::query_as!(VcPingChannel, "SELECT * FROM VcPingChannels")
+                .fetch_all(pool)
+                .await
+                .unwrap();
         // export to json
-        let json = serde_json::to_string(&user_id_guild_id).unwrap();
+        let json = serde_json::to_string(&PingListExport::PingList {
+            users: user_id_guild_id,
+            channels: vc_ping_channels,
+        })
+        .unwrap();
         // send json to user
         msg.channel_id.say(&ctx.http, json).await.unwrap();
     } else if msg.content.starts_with("$import") {
@@ -22,8 +44,11 @@ pub async fn handle_owner_message(ctx: &Context, msg: &Message) {
         let state = data.get::<State>().unwrap();
         let pool = &state.pool;
         let json = msg.content.replace("$import", "");
-        let user_id_guild_id: Vec<UserIDGuildID> =
-            serde_json::from_str(&json).expect("Failed to parse json");
+        let (user_id_guild_id, vc_ping_channels) =
+            match serde_json::from_str(&json).expect("Failed to parse json") {
+                PingListExport::PingList { users, channels } => (users, channels),
+                PingListExport::Users(users) => (users, Vec::new()),
+            };
         for user_id_guild_id in &user_id_guild_id {
             sqlx::query!(
                 "INSERT INTO UserIDGuildID (user_id, guild_id, disconnect_message) VALUES ($1, $2, $3)",
@@ -35,12 +60,43 @@ pub async fn handle_owner_message(ctx: &Context, msg: &Message) {
             .await
             .unwrap();
         }
+        for vc_ping_channel in &vc_ping_channels {
+            sqlx::query!(
+                "INSERT INTO VcPingChannels (user_id, guild_id, channel_id) VALUES ($1, $2, $3)",
+                vc_ping_channel.user_id,
+                vc_ping_channel.guild_id,
+                vc_ping_channel.channel_id
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+        }
         msg.channel_id
             .say(
                 &ctx.http,
-                format!("Imported {} rows", user_id_guild_id.len()),
+                format!(
+                    "Imported {} rows and {} channels",
+                    user_id_guild_id.len(),
+                    vc_ping_channels.len()
+                ),
             )
             .await
             .unwrap();
+    } else if msg.content == "$reload" {
+        // only swap the config if the new one is fully valid
+        let reply = match config::read_config() {
+            Ok(config) => {
+                let number_of_jokes = config.jokes.len();
+                let mut data = ctx.data.write().await;
+                data.insert::<Config>(config);
+                // the matchers contain the old global jokes
+                let state = data.get_mut::<State>().unwrap();
+                state.matchers.clear();
+                state.dm_matcher = None;
+                format!("Reloaded config with {} jokes", number_of_jokes)
+            }
+            Err(e) => format!("Failed to reload config, keeping the old one: {}", e),
+        };
+        msg.channel_id.say(&ctx.http, reply).await.unwrap();
     }
 }
@@ -0,0 +1,178 @@
+use serenity::all::{GuildId, Reaction, ReactionType};
+use serenity::prelude::*;
+use tracing::{debug, error, info};
+
+use crate::{get_joke_config, get_joke_overrides, State};
+
+/// Reactions that count as a downvote on a joke.
+const DOWNVOTE_EMOJIS: [&str; 2] = ["👎", "🗑️"];
+/// Downvotes after which a joke message is deleted, if the guild didn't configure it.
+pub const DEFAULT_DOWNVOTES_TO_DELETE: i64 = 3;
+/// A joke has to be made this often before its chance is lowered automatically.
+const MIN_EVENTS_TO_LOWER: i64 = 5;
+
+struct JokeEvent {
+    guild_id: i64,
+    joke_name: String,
+}
+
+/// The emoji as it is stored in `JokeFeedback`.
+fn downvote_emoji(emoji: &ReactionType) -> String {
+    emoji.to_string().trim_end_matches('\u{fe0f}').to_string()
+}
+
+fn is_downvote(emoji: &ReactionType) -> bool {
+    match emoji {
+        ReactionType::Unicode(emoji) => DOWNVOTE_EMOJIS
+            .iter()
+            // 🗑 is sometimes sent without the variation selector
+            .any(|downvote| {
+                downvote.trim_end_matches('\u{fe0f}') == emoji.trim_end_matches('\u{fe0f}')
+            }),
+        _ => false,
+    }
+}
+
+/// Returns the joke the bot made with the message, if it is a joke message.
+async fn get_joke_event(ctx: &Context, reaction: &Reaction) -> Option<JokeEvent> {
+    // other bots, e.g. ones that react to every message, don't vote
+    if !is_downvote(&reaction.emoji)
+        || reaction.user_id == Some(ctx.cache.current_user().id)
+        || reaction
+            .member
+            .as_ref()
+            .is_some_and(|member| member.user.bot)
+    {
+        return None;
+    }
+    let message_id = reaction.message_id.get() as i64;
+    sqlx::query_as!(
+        JokeEvent,
+        "SELECT guild_id, joke_name FROM JokeEvents WHERE reply_message_id = $1",
+        message_id
+    )
+    .fetch_optional(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap()
+}
+
+pub async fn handle_reaction_add(ctx: &Context, reaction: Reaction) {
+    let Some(event) = get_joke_event(ctx, &reaction).await else {
+        return;
+    };
+    let Some(user_id) = reaction.user_id else {
+        return;
+    };
+    debug!("Downvote on joke {} by {}", event.joke_name, user_id);
+    let pool = ctx.data.read().await.get::<State>().unwrap().pool.clone();
+    let message_id = reaction.message_id.get() as i64;
+    let user_id = user_id.get() as i64;
+    let emoji = downvote_emoji(&reaction.emoji);
+    sqlx::query!(
+        "INSERT INTO JokeFeedback (message_id, user_id, emoji, guild_id, joke_name) VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+        message_id,
+        user_id,
+        emoji,
+        event.guild_id,
+        event.joke_name
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let guild_id = GuildId::new(event.guild_id as u64);
+    let config = get_joke_config(ctx, guild_id).await;
+
+    // delete the joke once enough people downvoted it
+    let downvotes_to_delete = config
+        .downvotes_to_delete
+        .unwrap_or(DEFAULT_DOWNVOTES_TO_DELETE);
+    let downvotes = sqlx::query!(
+        r#"SELECT COUNT(DISTINCT user_id) AS "count!: i64" FROM JokeFeedback WHERE message_id = $1"#,
+        message_id
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap()
+    .count;
+    if downvotes_to_delete > 0 && downvotes >= downvotes_to_delete {
+        if let Err(e) = reaction
+            .channel_id
+            .delete_message(&ctx.http, reaction.message_id)
+            .await
+        {
+            error!("Error deleting downvoted joke: {:?}", e);
+        }
+    }
+
+    // a threshold of 0 disables lowering the chance
+    let Some(threshold) = config
+        .auto_lower_threshold
+        .filter(|threshold| *threshold > 0.0)
+    else {
+        return;
+    };
+    let stats = sqlx::query!(
+        r#"SELECT
+            (SELECT COUNT(*) FROM JokeEvents WHERE guild_id = $1 AND joke_name = $2 AND reply_message_id IS NOT NULL) AS "events!: i64",
+            (SELECT COUNT(DISTINCT message_id) FROM JokeFeedback WHERE guild_id = $1 AND joke_name = $2) AS "downvoted!: i64""#,
+        event.guild_id,
+        event.joke_name
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    if stats.events < MIN_EVENTS_TO_LOWER {
+        return;
+    }
+    let ratio = stats.downvoted as f64 / stats.events as f64;
+    if ratio <= threshold {
+        return;
+    }
+
+    // scale the guild chance by how well the joke is received, never raising it
+    let current = get_joke_overrides(ctx, guild_id)
+        .await
+        .get(&event.joke_name)
+        .and_then(|joke_override| joke_override.chance)
+        .unwrap_or(config.chance);
+    let lowered = (config.chance * (1.0 - ratio)).min(current);
+    if lowered < current {
+        info!(
+            "Lowering chance of joke {} in guild {} to {}",
+            event.joke_name, guild_id, lowered
+        );
+        sqlx::query!(
+            "INSERT INTO JokeOverride (guild_id, joke_name, enabled, chance) VALUES ($1, $2, TRUE, $3)
+            ON CONFLICT (guild_id, joke_name) DO UPDATE SET chance = $3",
+            event.guild_id,
+            event.joke_name,
+            lowered
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+}
+
+pub async fn handle_reaction_remove(ctx: &Context, reaction: Reaction) {
+    if get_joke_event(ctx, &reaction).await.is_none() {
+        return;
+    }
+    let Some(user_id) = reaction.user_id else {
+        return;
+    };
+    let message_id = reaction.message_id.get() as i64;
+    let user_id = user_id.get() as i64;
+    let emoji = downvote_emoji(&reaction.emoji);
+    // the user still downvotes the joke if they reacted with the other emoji too
+    sqlx::query!(
+        "DELETE FROM JokeFeedback WHERE message_id = $1 AND user_id = $2 AND emoji = $3",
+        message_id,
+        user_id,
+        emoji
+    )
+    .execute(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap();
+}
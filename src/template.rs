@@ -0,0 +1,594 @@
+//! Templates for joke messages.
+//!
+//! A template is plain text with two kinds of placeholders:
+//! - `--[key]--` is replaced with a value, where `key` is a capture group number,
+//!   a capture group name, `nickname`, `username`, `guild`, `channel`, `mention`,
+//!   `channel-mention` or `emoji:<name>`. It can be followed by transforms and a
+//!   fallback for missing values, e.g. `--[1|upper|title?someone]--`.
+//! - `{a|b|c}` is replaced with one of the alternatives, chosen at random.
+//!   Alternatives are templates themselves, so they can contain placeholders.
+//!
+//! `\` escapes the next character, so `\{` is a literal `{`.
+
+use fancy_regex::{Captures, Regex};
+use rand::{seq::SliceRandom, Rng};
+use serenity::all::{ChannelId, UserId};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Key {
+    Capture(usize),
+    NamedCapture(String),
+    Nickname,
+    Username,
+    Guild,
+    Channel,
+    Mention,
+    ChannelMention,
+    Emoji(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    Upper,
+    Lower,
+    Title,
+}
+
+impl Transform {
+    fn apply(self, value: &str) -> String {
+        match self {
+            Transform::Upper => value.to_uppercase(),
+            Transform::Lower => value.to_lowercase(),
+            Transform::Title => value
+                .split(' ')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) => {
+                            first.to_uppercase().collect::<String>()
+                                + &chars.as_str().to_lowercase()
+                        }
+                        None => String::new(),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Variable {
+        key: Key,
+        transforms: Vec<Transform>,
+        fallback: Option<String>,
+    },
+    Choice(Vec<Vec<Part>>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TemplateError {
+    /// position in characters
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at position {}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// The capture groups of a regex match, owned so they can outlive the message.
+#[derive(Clone, Debug, Default)]
+pub struct CaptureValues {
+    pub numbered: Vec<Option<String>>,
+    pub named: HashMap<String, String>,
+}
+
+impl CaptureValues {
+    pub fn new(regex: &Regex, captures: &Captures) -> Self {
+        CaptureValues {
+            numbered: captures
+                .iter()
+                .map(|capture| capture.map(|capture| capture.as_str().to_string()))
+                .collect(),
+            named: regex
+                .capture_names()
+                .flatten()
+                .filter_map(|name| {
+                    captures
+                        .name(name)
+                        .map(|capture| (name.to_string(), capture.as_str().to_string()))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Everything a template can refer to when it is rendered.
+#[derive(Clone, Default)]
+pub struct TemplateContext {
+    pub captures: CaptureValues,
+    pub nickname: String,
+    pub username: String,
+    pub guild: String,
+    pub channel: String,
+    pub user_id: Option<UserId>,
+    pub channel_id: Option<ChannelId>,
+    /// custom emojis of the guild, by name
+    pub emojis: HashMap<String, String>,
+}
+
+impl TemplateContext {
+    /// Fills in the capture groups of a regex match.
+    pub fn with_captures(mut self, captures: CaptureValues) -> Self {
+        self.captures = captures;
+        self
+    }
+
+    fn lookup(&self, key: &Key) -> Option<String> {
+        match key {
+            Key::Capture(i) => self.captures.numbered.get(*i).cloned().flatten(),
+            Key::NamedCapture(name) => self.captures.named.get(name).cloned(),
+            Key::Nickname => Some(self.nickname.clone()),
+            Key::Username => Some(self.username.clone()),
+            Key::Guild => Some(self.guild.clone()),
+            Key::Channel => Some(self.channel.clone()),
+            Key::Mention => self.user_id.map(|id| format!("<@{}>", id)),
+            Key::ChannelMention => self.channel_id.map(|id| format!("<#{}>", id)),
+            Key::Emoji(name) => {
+                self.emojis.get(name).cloned().or_else(|| {
+                    emojis::get_by_shortcode(name).map(|emoji| emoji.as_str().to_string())
+                })
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, message: impl Into<String>) -> TemplateError {
+        TemplateError {
+            position: self.pos,
+            message: message.into(),
+        }
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    /// Parses until the end, or until the end of the current alternative if in a choice.
+    fn parse_sequence(&mut self, in_choice: bool) -> Result<Vec<Part>, TemplateError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            if c == '\\' {
+                self.pos += 1;
+                match self.chars.get(self.pos) {
+                    Some(&escaped) => text.push(escaped),
+                    None => return Err(self.error("trailing `\\`")),
+                }
+                self.pos += 1;
+            } else if self.starts_with("--[") {
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.push(self.parse_variable()?);
+            } else if c == '{' {
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.push(self.parse_choice()?);
+            } else if in_choice && (c == '|' || c == '}') {
+                break;
+            } else if c == '}' {
+                return Err(self.error("`}` without matching `{`"));
+            } else {
+                text.push(c);
+                self.pos += 1;
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(parts)
+    }
+
+    fn parse_choice(&mut self) -> Result<Part, TemplateError> {
+        let start = self.pos;
+        // skip `{`
+        self.pos += 1;
+        let mut alternatives = vec![self.parse_sequence(true)?];
+        loop {
+            match self.chars.get(self.pos) {
+                Some('|') => {
+                    self.pos += 1;
+                    alternatives.push(self.parse_sequence(true)?);
+                }
+                Some('}') => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => {
+                    return Err(TemplateError {
+                        position: start,
+                        message: "`{` without matching `}`".to_string(),
+                    })
+                }
+            }
+        }
+        if alternatives.len() < 2 {
+            return Err(TemplateError {
+                position: start,
+                message: "a choice needs at least two alternatives separated by `|`".to_string(),
+            });
+        }
+        Ok(Part::Choice(alternatives))
+    }
+
+    fn parse_variable(&mut self) -> Result<Part, TemplateError> {
+        let start = self.pos;
+        // skip `--[`
+        self.pos += 3;
+        let body_start = self.pos;
+        while !self.starts_with("]--") {
+            if self.pos >= self.chars.len() {
+                return Err(TemplateError {
+                    position: start,
+                    message: "`--[` without matching `]--`".to_string(),
+                });
+            }
+            self.pos += 1;
+        }
+        let body: String = self.chars[body_start..self.pos].iter().collect();
+        // skip `]--`
+        self.pos += 3;
+
+        let error = |message: String| TemplateError {
+            position: start,
+            message,
+        };
+        let (spec, fallback) = match body.split_once('?') {
+            Some((spec, fallback)) => (spec, Some(fallback.to_string())),
+            None => (body.as_str(), None),
+        };
+        let mut spec = spec.split('|');
+        let key = match spec.next().unwrap().trim() {
+            "nickname" => Key::Nickname,
+            "username" => Key::Username,
+            "guild" => Key::Guild,
+            "channel" => Key::Channel,
+            "mention" => Key::Mention,
+            "channel-mention" => Key::ChannelMention,
+            key if key.starts_with("emoji:") && key.len() > "emoji:".len() => {
+                Key::Emoji(key["emoji:".len()..].to_string())
+            }
+            key if !key.is_empty() && key.chars().all(|c| c.is_ascii_digit()) => Key::Capture(
+                key.parse()
+                    .map_err(|_| error(format!("invalid capture group `{}`", key)))?,
+            ),
+            key if key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+            {
+                Key::NamedCapture(key.to_string())
+            }
+            key => return Err(error(format!("invalid variable `{}`", key))),
+        };
+        let transforms = spec
+            .map(|transform| match transform.trim() {
+                "upper" => Ok(Transform::Upper),
+                "lower" => Ok(Transform::Lower),
+                "title" => Ok(Transform::Title),
+                transform => Err(error(format!("unknown transform `{}`", transform))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Part::Variable {
+            key,
+            transforms,
+            fallback,
+        })
+    }
+}
+
+fn render_parts(parts: &[Part], context: &TemplateContext, rng: &mut impl Rng, out: &mut String) {
+    for part in parts {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Variable {
+                key,
+                transforms,
+                fallback,
+            } => {
+                // a missing value without a fallback is left out
+                let value = context
+                    .lookup(key)
+                    .or_else(|| fallback.clone())
+                    .unwrap_or_default();
+                out.push_str(
+                    &transforms
+                        .iter()
+                        .fold(value, |value, transform| transform.apply(&value)),
+                );
+            }
+            Part::Choice(alternatives) => {
+                let alternative = alternatives.choose(rng).unwrap();
+                render_parts(alternative, context, rng, out);
+            }
+        }
+    }
+}
+
+fn keys<'a>(parts: &'a [Part], out: &mut Vec<&'a Key>) {
+    for part in parts {
+        match part {
+            Part::Text(_) => {}
+            Part::Variable { key, .. } => out.push(key),
+            Part::Choice(alternatives) => {
+                for alternative in alternatives {
+                    keys(alternative, out);
+                }
+            }
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, TemplateError> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+        };
+        let parts = parser.parse_sequence(false)?;
+        Ok(Template {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// All variables the template refers to.
+    pub fn keys(&self) -> Vec<&Key> {
+        let mut out = Vec::new();
+        keys(&self.parts, &mut out);
+        out
+    }
+
+    /// Whether the template refers to any capture group.
+    pub fn uses_captures(&self) -> bool {
+        self.keys()
+            .iter()
+            .any(|key| matches!(key, Key::Capture(_) | Key::NamedCapture(_)))
+    }
+
+    /// Checks that every capture group the template refers to exists in the regex.
+    pub fn check_captures(&self, regex: &Regex) -> Result<(), String> {
+        self.check_captures_with(regex, &HashSet::new())
+    }
+
+    /// Like `check_captures`, but named capture groups may also be one of `names`,
+    /// e.g. groups remembered from earlier messages.
+    pub fn check_captures_with(
+        &self,
+        regex: &Regex,
+        names: &HashSet<String>,
+    ) -> Result<(), String> {
+        for key in self.keys() {
+            match key {
+                Key::Capture(i) if *i >= regex.captures_len() => {
+                    return Err(format!(
+                        "capture group {} does not exist, the regex only has {}",
+                        i,
+                        regex.captures_len() - 1
+                    ));
+                }
+                Key::NamedCapture(name)
+                    if !names.contains(name)
+                        && !regex.capture_names().flatten().any(|n| n == name) =>
+                {
+                    return Err(format!("capture group `{}` does not exist", name));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, context: &TemplateContext, rng: &mut impl Rng) -> String {
+        let mut out = String::new();
+        render_parts(&self.parts, context, rng, &mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn context() -> TemplateContext {
+        TemplateContext {
+            captures: CaptureValues {
+                numbered: vec![
+                    Some("I'm tired".to_string()),
+                    Some("tired".to_string()),
+                    None,
+                ],
+                named: HashMap::from([("who".to_string(), "Boo".to_string())]),
+            },
+            nickname: "nick".to_string(),
+            username: "user".to_string(),
+            guild: "guild".to_string(),
+            channel: "general".to_string(),
+            user_id: Some(UserId::new(1)),
+            channel_id: Some(ChannelId::new(2)),
+            emojis: HashMap::from([("car".to_string(), "<:car:3>".to_string())]),
+        }
+    }
+
+    fn render(source: &str) -> String {
+        Template::parse(source)
+            .unwrap()
+            .render(&context(), &mut StdRng::seed_from_u64(0))
+    }
+
+    fn error(source: &str) -> TemplateError {
+        Template::parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn renders_variables() {
+        assert_eq!(render("Hi --[1]--, I'm dad"), "Hi tired, I'm dad");
+        assert_eq!(render("--[who]-- who?"), "Boo who?");
+        assert_eq!(
+            render("--[nickname]-- --[username]-- --[guild]-- --[channel]--"),
+            "nick user guild general"
+        );
+        assert_eq!(render("--[mention]-- --[channel-mention]--"), "<@1> <#2>");
+        assert_eq!(render("--[emoji:car]-- --[emoji:smile]--"), "<:car:3> 😄");
+        assert_eq!(render("--[1|upper]-- --[0|title]--"), "TIRED I'm Tired");
+    }
+
+    #[test]
+    fn parses_choices() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let template = Template::parse("{a|b|--[1]--}!").unwrap();
+        for _ in 0..20 {
+            let rendered = template.render(&context(), &mut rng);
+            assert!(["a!", "b!", "tired!"].contains(&rendered.as_str()));
+        }
+        assert_eq!(render("{x|x}{|}"), "x");
+        assert_eq!(render("{{a|a}|{a|a}}"), "a");
+    }
+
+    #[test]
+    fn rejects_unbalanced_templates() {
+        assert_eq!(error("hi {a|b").position, 3);
+        assert_eq!(error("hi {a|b").message, "`{` without matching `}`");
+        assert_eq!(error("hi }").message, "`}` without matching `{`");
+        assert_eq!(
+            error("{a}").message,
+            "a choice needs at least two alternatives separated by `|`"
+        );
+        assert_eq!(error("x --[1").position, 2);
+        assert_eq!(error("x --[1").message, "`--[` without matching `]--`");
+        assert_eq!(error("--[]--").message, "invalid variable ``");
+        assert_eq!(error("--[1|shout]--").message, "unknown transform `shout`");
+        assert_eq!(error("trailing \\").message, "trailing `\\`");
+    }
+
+    #[test]
+    fn handles_escapes() {
+        assert_eq!(render("\\{a|b\\}"), "{a|b}");
+        assert_eq!(render("\\--[1]--"), "--[1]--");
+        assert_eq!(render("a \\\\ b"), "a \\ b");
+        assert_eq!(render("{a\\|b|a\\|b}"), "a|b");
+        // only `--[` starts a variable
+        assert_eq!(render("a -- b ] c"), "a -- b ] c");
+    }
+
+    #[test]
+    fn uses_fallbacks() {
+        assert_eq!(render("--[2?someone]--"), "someone");
+        assert_eq!(render("--[5|upper?someone]--"), "SOMEONE");
+        assert_eq!(render("--[1?someone]--"), "tired");
+        assert_eq!(render("--[missing?nobody]--"), "nobody");
+        // a missing value without a fallback is left out
+        assert_eq!(render("[--[2]--]"), "[]");
+    }
+
+    #[test]
+    fn checks_captures() {
+        let regex = Regex::new("(i'm) (?<what>\\w+)").unwrap();
+        let check = |source: &str| Template::parse(source).unwrap().check_captures(&regex);
+        assert_eq!(check("--[0]-- --[1]-- --[2]-- --[what]--"), Ok(()));
+        assert_eq!(
+            check("--[3]--"),
+            Err("capture group 3 does not exist, the regex only has 2".to_string())
+        );
+        assert_eq!(
+            check("{a|--[who]--}"),
+            Err("capture group `who` does not exist".to_string())
+        );
+
+        let names = HashSet::from(["who".to_string()]);
+        let template = Template::parse("--[who]-- --[what]--").unwrap();
+        assert_eq!(template.check_captures_with(&regex, &names), Ok(()));
+        assert!(Template::parse("--[nickname]--")
+            .unwrap()
+            .check_captures(&regex)
+            .is_ok());
+        assert!(!Template::parse("--[nickname]--").unwrap().uses_captures());
+        assert!(Template::parse("{a|--[what]--}").unwrap().uses_captures());
+    }
+
+    /// How messages were rendered before templates, by replacing each placeholder.
+    fn render_legacy(source: &str, context: &TemplateContext) -> String {
+        let mut message = source.to_string();
+        for (i, capture) in context.captures.numbered.iter().enumerate() {
+            if let Some(capture) = capture {
+                message = message.replace(&format!("--[{}]--", i), capture);
+            }
+        }
+        message
+            .replace("--[nickname]--", &context.nickname)
+            .replace("--[username]--", &context.username)
+            .replace("--[guild]--", &context.guild)
+            .replace("--[channel]--", &context.channel)
+    }
+
+    #[test]
+    fn renders_config_messages_unchanged() {
+        let config = crate::config::parse_config(include_str!("../data/config.json")).unwrap();
+        let context = TemplateContext {
+            captures: CaptureValues {
+                numbered: (0..10).map(|i| Some(format!("capture {}", i))).collect(),
+                named: HashMap::new(),
+            },
+            ..context()
+        };
+        let mut rendered = 0;
+        for joke in &config.jokes {
+            for message in &joke.message {
+                let source = message.template.source();
+                // choices and escapes didn't exist before templates
+                if source.contains(['{', '}', '\\']) {
+                    continue;
+                }
+                assert_eq!(
+                    message
+                        .template
+                        .render(&context, &mut StdRng::seed_from_u64(0)),
+                    render_legacy(source, &context),
+                    "joke {}",
+                    joke.name
+                );
+                rendered += 1;
+            }
+        }
+        assert!(rendered > 0);
+    }
+}
@@ -1,4 +1,8 @@
-use serenity::all::{CreateEmbed, CreateEmbedAuthor, CreateInvite, CreateMessage};
+mod notifications;
+
+pub use notifications::*;
+
+use serenity::all::{CreateInvite, CreateMessage, GuildChannel, GuildId, Mentionable};
 use serenity::prelude::*;
 use serenity::{all::UserId, model::voice::VoiceState};
 use std::time::Duration;
@@ -6,6 +10,34 @@ use tracing::{debug, error};
 
 use crate::{get_numer_of_users_in_channel, State, UserIDGuildID};
 
+/// Seconds an invite in the direct messages about a VC stays valid.
+const INVITE_MAX_AGE: u32 = 24 * 60 * 60;
+
+/// Returns the users on the ping list of the guild who want to hear about the voice channel,
+/// because they subscribed to it, to its category, or to every channel.
+pub async fn get_vc_ping_recipients(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel: &GuildChannel,
+) -> Vec<UserIDGuildID> {
+    let guild_id = guild_id.get() as i64;
+    let channel_id = channel.id.get() as i64;
+    let category_id = channel.parent_id.map(|parent_id| parent_id.get() as i64);
+    sqlx::query_as!(
+        UserIDGuildID,
+        "SELECT * FROM UserIDGuildID WHERE guild_id = $1 AND (
+            NOT EXISTS (SELECT 1 FROM VcPingChannels WHERE VcPingChannels.user_id = UserIDGuildID.user_id AND VcPingChannels.guild_id = $1)
+            OR EXISTS (SELECT 1 FROM VcPingChannels WHERE VcPingChannels.user_id = UserIDGuildID.user_id AND VcPingChannels.guild_id = $1 AND VcPingChannels.channel_id IN ($2, $3))
+        )",
+        guild_id,
+        channel_id,
+        category_id
+    )
+    .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap()
+}
+
 pub async fn handle_voice_state_update(ctx: &Context, old: Option<VoiceState>, new: VoiceState) {
     debug!("voice_state_update: \nold: {:?} \nnew: {:?}", old, new);
     let channel = match new.channel_id {
@@ -22,8 +54,12 @@ pub async fn handle_voice_state_update(ctx: &Context, old: Option<VoiceState>, n
 
     let channel = channel.guild().unwrap();
     let number_of_users_in_channel = get_numer_of_users_in_channel(ctx, &new).await;
+    // the channel, if the user is the first to join it
+    let started_channel = new
+        .channel_id
+        .filter(|_| old.is_none() && number_of_users_in_channel == 1);
     // if user joins a voice channel
-    if old.is_none() && new.channel_id.is_some() && number_of_users_in_channel == 1 {
+    if let Some(channel_id) = started_channel {
         debug!("User joined channel");
         // wait one minute
         tokio::time::sleep(Duration::from_secs(60)).await;
@@ -37,72 +73,74 @@ pub async fn handle_voice_state_update(ctx: &Context, old: Option<VoiceState>, n
         // add channel to map
         let mut data = ctx.data.write().await;
         let state = data.get_mut::<State>().unwrap();
-        state.occupied_channels.insert(new.channel_id.unwrap());
+        state.occupied_channels.insert(channel_id);
         drop(data);
-        let guild_id = new.guild_id.unwrap().get() as i64;
+        let guild = new.guild_id.unwrap();
+        let member = new.member.as_ref().unwrap();
 
-        let to_ping_user_ids: Vec<UserIDGuildID> = sqlx::query_as!(
-            UserIDGuildID,
-            "SELECT * FROM UserIDGuildID WHERE guild_id = $1",
-            guild_id
-        )
-        .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
-        .await
-        .unwrap();
+        let config = get_vc_notification_config(ctx, guild).await;
+        if let Some(config) = config.as_ref().filter(|config| config.posts()) {
+            let description = format!(
+                "{} Started VC in {}",
+                member.display_name(),
+                channel_id.mention()
+            );
+            post_vc_notification(ctx, config, vc_embed(ctx, guild, member, description)).await;
+        }
+        if config.as_ref().is_some_and(|config| !config.send_dms) {
+            return;
+        }
 
-        for user_id_guild_id in to_ping_user_ids {
-            // check that user is not in the channel
-            if user_id_guild_id.user_id == new.user_id.get() as i64 {
-                continue;
-            }
-            let user_id = user_id_guild_id.user_id;
+        let to_ping_user_ids = get_vc_ping_recipients(ctx, guild, &channel).await;
+        // everyone but the member who started the VC
+        let to_ping_user_ids: Vec<i64> = to_ping_user_ids
+            .into_iter()
+            .map(|user_id_guild_id| user_id_guild_id.user_id)
+            .filter(|user_id| *user_id != new.user_id.get() as i64)
+            .collect();
+        if to_ping_user_ids.is_empty() {
+            return;
+        }
+
+        let channel_name = channel_id.name(&ctx).await.unwrap();
+        let embed = vc_embed(
+            ctx,
+            guild,
+            member,
+            format!("{} Started VC in {}", member.display_name(), channel_name),
+        );
+
+        for user_id in to_ping_user_ids {
             let user = match ctx.cache.user(user_id as u64).map(|u| u.clone()) {
                 Some(user) => user,
                 None => {
                     // get user from api
-                    ctx.http
-                        .get_user(UserId::new(user_id as u64))
-                        .await
-                        .unwrap()
+                    match ctx.http.get_user(UserId::new(user_id as u64)).await {
+                        Ok(user) => user,
+                        Err(e) => {
+                            error!("Error fetching user {}: {:?}", user_id, e);
+                            continue;
+                        }
+                    }
                 }
             };
-            let channel_name = new.channel_id.unwrap().name(&ctx).await.unwrap();
-            let invite = channel
-                .create_invite(&ctx.http, CreateInvite::new().max_uses(1))
-                .await
-                .unwrap();
-            let guild = &new
-                .guild_id
-                .unwrap()
-                .to_guild_cached(&ctx.cache)
-                .unwrap()
-                .clone();
-            if let Err(e) = user
-                .direct_message(
+            // a single-use invite for every user, the embed links to the guild without one
+            let mut embed = embed.clone();
+            match channel
+                .create_invite(
                     &ctx.http,
-                    CreateMessage::new().add_embed(
-                        CreateEmbed::new()
-                            .title(new.guild_id.unwrap().name(&ctx.cache).unwrap())
-                            .url(invite.url())
-                            .author(
-                                CreateEmbedAuthor::new(new.member.as_ref().unwrap().display_name())
-                                    // .url("https://discord.gg/invite")
-                                    .icon_url(
-                                        new.member.as_ref().unwrap().user.avatar_url().unwrap_or(
-                                            new.member.as_ref().unwrap().user.default_avatar_url(),
-                                        ),
-                                    ),
-                            )
-                            .description(format!(
-                                "{} Started VC in {}",
-                                new.member.as_ref().unwrap().display_name(),
-                                channel_name,
-                            ))
-                            .thumbnail(guild.icon_url().unwrap()),
-                    ),
+                    CreateInvite::new().max_uses(1).max_age(INVITE_MAX_AGE),
                 )
                 .await
             {
+                Ok(invite) => embed = embed.url(invite.url()),
+                Err(e) => error!("Error creating invite: {:?}", e),
+            }
+            if let Err(e) = user
+                .direct_message(&ctx.http, CreateMessage::new().add_embed(embed))
+                .await
+            {
+                // e.g. the user doesn't accept direct messages from the guild
                 error!("Error sending message: {:?}", e);
             }
         }
@@ -121,68 +159,59 @@ pub async fn handle_voice_state_update(ctx: &Context, old: Option<VoiceState>, n
                 return;
             }
             drop(data);
-            let guild_id = new.guild_id.unwrap().get() as i64;
-            let to_ping_user_ids: Vec<UserIDGuildID> = sqlx::query_as!(
-                UserIDGuildID,
-                "SELECT * FROM UserIDGuildID WHERE guild_id = $1",
-                guild_id
-            )
-            .fetch_all(&ctx.data.read().await.get::<State>().unwrap().pool)
-            .await
-            .unwrap();
+            let guild = new.guild_id.unwrap();
+            let member = new.member.as_ref().unwrap();
+
+            let config = get_vc_notification_config(ctx, guild).await;
+            if let Some(config) = config
+                .as_ref()
+                .filter(|config| config.posts() && config.disconnect_message)
+            {
+                let description = format!(
+                    "{} Stopped VC in {}",
+                    member.display_name(),
+                    channel_id.mention()
+                );
+                post_vc_notification(ctx, config, vc_embed(ctx, guild, member, description)).await;
+            }
+            if config.as_ref().is_some_and(|config| !config.send_dms) {
+                return;
+            }
+
+            let to_ping_user_ids = get_vc_ping_recipients(ctx, guild, &channel).await;
 
+            let channel_name = channel_id.name(&ctx).await.unwrap();
+            let embed = vc_embed(
+                ctx,
+                guild,
+                member,
+                format!("{} Stopped VC in {}", member.display_name(), channel_name),
+            );
             for user_id_guild_id in to_ping_user_ids {
                 let send_disconnect_message = user_id_guild_id.disconnect_message.unwrap_or(true);
                 if !send_disconnect_message {
                     continue;
                 }
                 let user_id = user_id_guild_id.user_id;
+                // don't send message to user who left
+                if user_id == new.user_id.get() as i64 {
+                    continue;
+                }
                 let user = match ctx.cache.user(user_id as u64).map(|u| u.clone()) {
                     Some(user) => user,
                     None => {
                         // get user from api
-                        ctx.http
-                            .get_user(UserId::new(user_id as u64))
-                            .await
-                            .unwrap()
+                        match ctx.http.get_user(UserId::new(user_id as u64)).await {
+                            Ok(user) => user,
+                            Err(e) => {
+                                error!("Error fetching user {}: {:?}", user_id, e);
+                                continue;
+                            }
+                        }
                     }
                 };
-                // don't send message to user who left
-                if user_id == new.user_id.get() as i64 {
-                    continue;
-                }
-
-                let channel_name = channel_id.name(&ctx).await.unwrap();
-                let guild = &new
-                    .guild_id
-                    .unwrap()
-                    .to_guild_cached(&ctx.cache)
-                    .unwrap()
-                    .clone();
                 if let Err(e) = user
-                    .direct_message(
-                        &ctx.http,
-                        CreateMessage::new().add_embed(
-                            CreateEmbed::new()
-                                .title(new.guild_id.unwrap().name(&ctx.cache).unwrap())
-                                .author(
-                                    CreateEmbedAuthor::new(
-                                        new.member.as_ref().unwrap().display_name(),
-                                    )
-                                    .icon_url(
-                                        new.member.as_ref().unwrap().user.avatar_url().unwrap_or(
-                                            new.member.as_ref().unwrap().user.default_avatar_url(),
-                                        ),
-                                    ),
-                                )
-                                .description(format!(
-                                    "{} Stopped VC in {}",
-                                    new.member.as_ref().unwrap().display_name(),
-                                    channel_name,
-                                ))
-                                .thumbnail(guild.icon_url().unwrap()),
-                        ),
-                    )
+                    .direct_message(&ctx.http, CreateMessage::new().add_embed(embed.clone()))
                     .await
                 {
                     error!("Error sending message: {:?}", e);
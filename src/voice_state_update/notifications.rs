@@ -0,0 +1,177 @@
+use serenity::all::{
+    Builder, ChannelId, CreateAllowedMentions, CreateEmbed, CreateEmbedAuthor, CreateMessage,
+    ExecuteWebhook, GuildId, Member, RoleId, WebhookId,
+};
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::State;
+
+/// Where a guild posts about its VCs, as stored in the `VcNotificationConfig` table.
+pub struct VcNotificationConfig {
+    pub guild_id: i64,
+    /// channel to post in, unless `webhook_url` is set
+    pub channel_id: Option<i64>,
+    pub webhook_url: Option<String>,
+    /// role mentioned in the posts
+    pub role_id: Option<i64>,
+    /// whether subscribed users still get a direct message
+    pub send_dms: bool,
+    /// whether the end of a VC is posted too
+    pub disconnect_message: bool,
+}
+
+impl VcNotificationConfig {
+    /// Whether the guild has somewhere to post.
+    pub fn posts(&self) -> bool {
+        self.channel_id.is_some() || self.webhook_url.is_some()
+    }
+}
+
+/// Hosts that webhook urls may point to, so the token is never sent anywhere else.
+const WEBHOOK_HOSTS: [&str; 6] = [
+    "discord.com",
+    "canary.discord.com",
+    "ptb.discord.com",
+    "discordapp.com",
+    "canary.discordapp.com",
+    "ptb.discordapp.com",
+];
+
+/// Splits a webhook url like `https://discord.com/api/webhooks/<id>/<token>` into its id and token.
+pub fn parse_webhook_url(url: &str) -> Option<(WebhookId, &str)> {
+    let (host, path) = url.strip_prefix("https://")?.split_once('/')?;
+    if !WEBHOOK_HOSTS.contains(&host) {
+        return None;
+    }
+    let (webhook_id, token) = path.strip_prefix("api/webhooks/")?.split_once('/')?;
+    let webhook_id = webhook_id.parse::<u64>().ok().filter(|id| *id != 0)?;
+    if token.is_empty() || token.contains(['/', '?', '#']) {
+        return None;
+    }
+    Some((WebhookId::new(webhook_id), token))
+}
+
+/// Returns the notification settings of a guild, `None` if it never configured them.
+pub async fn get_vc_notification_config(
+    ctx: &Context,
+    guild_id: GuildId,
+) -> Option<VcNotificationConfig> {
+    let gid = guild_id.get() as i64;
+    sqlx::query_as!(
+        VcNotificationConfig,
+        "SELECT * FROM VcNotificationConfig WHERE guild_id = $1",
+        gid
+    )
+    .fetch_optional(&ctx.data.read().await.get::<State>().unwrap().pool)
+    .await
+    .unwrap()
+}
+
+/// Builds the embed about a member starting or stopping a VC.
+pub fn vc_embed(
+    ctx: &Context,
+    guild_id: GuildId,
+    member: &Member,
+    description: String,
+) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .author(
+            CreateEmbedAuthor::new(member.display_name()).icon_url(
+                member
+                    .user
+                    .avatar_url()
+                    .unwrap_or(member.user.default_avatar_url()),
+            ),
+        )
+        .description(description);
+    if let Some(guild) = guild_id.to_guild_cached(&ctx.cache) {
+        embed = embed.title(guild.name.clone());
+        // guilds without an icon just don't get a thumbnail
+        if let Some(icon_url) = guild.icon_url() {
+            embed = embed.thumbnail(icon_url);
+        }
+    }
+    embed
+}
+
+/// Posts the embed in the channel or through the webhook of the guild, mentioning its role.
+pub async fn post_vc_notification(
+    ctx: &Context,
+    config: &VcNotificationConfig,
+    embed: CreateEmbed,
+) {
+    let role_id = config.role_id.map(|role_id| RoleId::new(role_id as u64));
+    let mentions = CreateAllowedMentions::new().roles(role_id);
+
+    if let Some(webhook_url) = &config.webhook_url {
+        // the id and token are all that's needed, so the webhook isn't fetched first
+        let Some((webhook_id, token)) = parse_webhook_url(webhook_url) else {
+            error!("Invalid VC webhook url in guild {}", config.guild_id);
+            return;
+        };
+        let mut builder = ExecuteWebhook::new()
+            .embed(embed)
+            .allowed_mentions(mentions);
+        if let Some(role_id) = role_id {
+            builder = builder.content(role_id.mention().to_string());
+        }
+        if let Err(e) = builder.execute(&ctx.http, (webhook_id, token, false)).await {
+            error!("Error posting VC notification: {:?}", e);
+        }
+    } else if let Some(channel_id) = config.channel_id {
+        let mut builder = CreateMessage::new().embed(embed).allowed_mentions(mentions);
+        if let Some(role_id) = role_id {
+            builder = builder.content(role_id.mention().to_string());
+        }
+        if let Err(e) = ChannelId::new(channel_id as u64)
+            .send_message(&ctx.http, builder)
+            .await
+        {
+            error!("Error posting VC notification: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "ig5AO-wdVWpCBtUUMxmgsWryqgsW3DChbKYOINftJ4DCrUbnkedoYZD0VOH1QLr-S3sV";
+
+    #[test]
+    fn parses_discord_webhook_urls() {
+        for host in WEBHOOK_HOSTS {
+            let url = format!("https://{}/api/webhooks/245037420704169985/{}", host, TOKEN);
+            assert_eq!(
+                parse_webhook_url(&url),
+                Some((WebhookId::new(245037420704169985), TOKEN)),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_webhook_urls() {
+        for url in [
+            "",
+            "not a url",
+            "https://discord.com/api/webhooks/245037420704169985",
+            "https://discord.com/api/webhooks/245037420704169985/",
+            "https://discord.com/api/webhooks/abc/token",
+            "https://discord.com/api/webhooks/0/token",
+            "https://discord.com/api/webhooks/245037420704169985/token/extra",
+            "https://discord.com/api/webhooks/245037420704169985/token?wait=true",
+            "https://discord.com/api/channels/245037420704169985/token",
+            "http://discord.com/api/webhooks/245037420704169985/token",
+            "https://example.com/api/webhooks/245037420704169985/token",
+            "https://discord.com.example.com/api/webhooks/245037420704169985/token",
+            "https://evil.discord.com/api/webhooks/245037420704169985/token",
+            "https://discord.com@example.com/api/webhooks/245037420704169985/token",
+            "https://discord.com:8080/api/webhooks/245037420704169985/token",
+        ] {
+            assert_eq!(parse_webhook_url(url), None, "{}", url);
+        }
+    }
+}